@@ -0,0 +1,173 @@
+/*
+  (format template args...) fills the placeholders of a template:
+
+    {}        the next argument
+    {1}       the argument at a position, counting from 0
+    {name}    :name in the last argument, a map or a record
+
+  A placeholder can end with a spec after a colon, [[fill]align][0][width][.precision] as in Rust: {:>6} right
+  aligns in 6 characters, {:*^9} centers padding with *, {:06.2} zero pads a number with 2 decimals and {:.3}
+  keeps 3 characters of a string. Numbers align right and everything else left by default. {{ and }} stand for
+  braces. Values other than strings and numbers are written with show, like print does.
+
+  (str x ...) joins its arguments the same way without a template, it's what #"Hello {name}" reads as.
+*/
+
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::generic::show;
+use crate::evaluator::value::{lookup, EnvHead, Value};
+use crate::harp_err;
+
+#[derive(Default)]
+struct Spec {
+  fill: Option<char>,
+  align: Option<char>,
+  zero: bool,
+  width: usize,
+  precision: Option<usize>,
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, HarpError> {
+  let chars: Vec<char> = spec.chars().collect();
+  let mut res = Spec::default();
+  let mut i = 0;
+  let is_align = |c: Option<&char>| matches!(c, Some('<') | Some('>') | Some('^'));
+  if is_align(chars.get(1)) {
+    res.fill = Some(chars[0]);
+    res.align = Some(chars[1]);
+    i = 2;
+  } else if is_align(chars.first()) {
+    res.align = Some(chars[0]);
+    i = 1;
+  }
+  if chars.get(i) == Some(&'0') {
+    res.zero = true;
+    i += 1;
+  }
+  let digits = |i: &mut usize| {
+    let start = *i;
+    while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+      *i += 1;
+    }
+    chars[start..*i].iter().collect::<String>().parse::<usize>().ok()
+  };
+  res.width = digits(&mut i).unwrap_or(0);
+  if chars.get(i) == Some(&'.') {
+    i += 1;
+    res.precision = match digits(&mut i) {
+      Some(precision) => Some(precision),
+      None => return harp_err!("Format expected a precision after . in {{:{}}}", spec),
+    };
+  }
+  if i < chars.len() {
+    return harp_err!("Format can't read the spec {{:{}}}", spec);
+  }
+  Ok(res)
+}
+
+// The text of a value on its own, strings as they are and everything else as print writes it
+fn text(value: Value, env: &mut EnvHead) -> Result<String, HarpError> {
+  match value {
+    Value::String(s) => Ok(s),
+    value => show(value, env),
+  }
+}
+
+fn apply_spec(value: Value, spec: &Spec, env: &mut EnvHead) -> Result<String, HarpError> {
+  let (body, numeric) = match (&value, spec.precision) {
+    (Value::Number(n), Some(precision)) => (format!("{:.*}", precision, n), true),
+    (Value::Number(_), None) => (value.to_string(), true),
+    (_, Some(precision)) => (text(value, env)?.chars().take(precision).collect(), false),
+    (_, None) => (text(value, env)?, false),
+  };
+
+  let len = body.chars().count();
+  if len >= spec.width {
+    return Ok(body);
+  }
+  let pad = spec.width - len;
+  if spec.zero && numeric && spec.align.is_none() {
+    let (sign, digits) = match body.strip_prefix('-') {
+      Some(digits) => ("-", digits),
+      None => ("", body.as_str()),
+    };
+    return Ok(format!("{}{}{}", sign, "0".repeat(pad), digits));
+  }
+
+  let fill = spec.fill.unwrap_or(' ').to_string();
+  let (before, after) = match spec.align.unwrap_or(if numeric { '>' } else { '<' }) {
+    '>' => (pad, 0),
+    '^' => (pad / 2, pad - pad / 2),
+    _ => (0, pad),
+  };
+  Ok(format!("{}{}{}", fill.repeat(before), body, fill.repeat(after)))
+}
+
+// The argument a placeholder names
+fn argument(name: &str, args: &[Value], next: &mut usize) -> Result<Value, HarpError> {
+  if name.is_empty() {
+    *next += 1;
+    return match args.get(*next - 1) {
+      Some(value) => Ok(value.clone()),
+      None => harp_err!("Format expected an argument for placeholder {}", *next),
+    };
+  }
+  if let Ok(index) = name.parse::<usize>() {
+    return match args.get(index) {
+      Some(value) => Ok(value.clone()),
+      None => harp_err!("Format expected an argument for {{{}}}", index),
+    };
+  }
+  let key = Value::Atom(format!(":{}", name));
+  match args.last().and_then(|named| lookup(named, &key)) {
+    Some(value) => Ok(value.clone()),
+    None => harp_err!("Format has no value for {{{}}}", name),
+  }
+}
+
+pub fn std_format(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let template = match &args[0] {
+    Value::String(template) => template.clone(),
+    v => return harp_err!("Format expected a template string, but got {}", v),
+  };
+  let args = &args[1..];
+
+  let mut res = String::new();
+  let mut next = 0;
+  let mut chars = template.chars().peekable();
+  while let Some(chr) = chars.next() {
+    match chr {
+      '{' | '}' if chars.peek() == Some(&chr) => {
+        chars.next();
+        res.push(chr);
+      }
+      '{' => {
+        let mut placeholder = String::new();
+        loop {
+          match chars.next() {
+            Some('}') => break,
+            Some(c) => placeholder.push(c),
+            None => return harp_err!("Format expected }} to close {{{}", placeholder),
+          }
+        }
+        let (name, spec) = match placeholder.split_once(':') {
+          Some((name, spec)) => (name, parse_spec(spec)?),
+          None => (placeholder.as_str(), Spec::default()),
+        };
+        let value = argument(name.trim(), args, &mut next)?;
+        res.push_str(&apply_spec(value, &spec, env)?);
+      }
+      '}' => return harp_err!("Format found a }} without a {{, write }}}} for a brace"),
+      chr => res.push(chr),
+    }
+  }
+  Ok(Value::String(res))
+}
+
+pub fn std_str(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let mut res = String::new();
+  for arg in args {
+    res.push_str(&text(arg, env)?);
+  }
+  Ok(Value::String(res))
+}
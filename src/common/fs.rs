@@ -0,0 +1,136 @@
+/*
+  The fs/ functions. Paths are strings, relative ones are taken from the working directory. Failures such as a
+  missing file or a permission problem are runtime errors naming the function and the path, so scripts can
+  catch them with try:
+
+    (try (fs/read-string "save.txt") (catch e ""))
+
+  (fs/join "saves" "slot1.txt") and (fs/normalize "saves/../maps/./a.map") only work on the text of a path and
+  never touch the disk.
+
+  They're part of the standard environment, so a script can read and write whatever files the host process can.
+*/
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::value::{EnvHead, Value};
+use crate::harp_err;
+
+fn io_err<T>(form: &str, path: &str, err: io::Error) -> Result<T, HarpError> {
+  harp_err!("{} failed for {}: {}", form, path, err)
+}
+
+fn path_string(path: PathBuf) -> String {
+  path.to_string_lossy().into_owned()
+}
+
+// (fs/join "a" "b" "c.txt") is "a/b/c.txt", an absolute part starts over from itself
+pub fn std_fs_join(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let mut path = PathBuf::new();
+  for arg in &args {
+    match arg {
+      Value::String(part) => path.push(part),
+      v => return harp_err!("fs/join expected a string, but got {}", v),
+    }
+  }
+  Ok(Value::String(path_string(path)))
+}
+
+// Drops . and resolves .. against the parts before it, keeping the .. which climb above a relative path
+fn normalize(path: &str) -> PathBuf {
+  let mut res = PathBuf::new();
+  let mut depth = 0;
+  for component in Path::new(path).components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir if depth > 0 => {
+        res.pop();
+        depth -= 1;
+      }
+      // The parent of the root is the root
+      Component::ParentDir if res.has_root() => {}
+      Component::ParentDir => res.push(".."),
+      Component::Normal(part) => {
+        res.push(part);
+        depth += 1;
+      }
+      root => res.push(root),
+    }
+  }
+  if res.as_os_str().is_empty() {
+    res.push(".");
+  }
+  res
+}
+
+fn read_string(path: String) -> Result<String, HarpError> {
+  fs::read_to_string(&path).or_else(|err| io_err("fs/read-string", &path, err))
+}
+
+fn read_lines(path: String) -> Result<Vec<String>, HarpError> {
+  match fs::read_to_string(&path) {
+    Ok(text) => Ok(text.lines().map(str::to_string).collect()),
+    Err(err) => io_err("fs/read-lines", &path, err),
+  }
+}
+
+fn write_string(path: String, text: String) -> Result<(), HarpError> {
+  fs::write(&path, text).or_else(|err| io_err("fs/write-string", &path, err))
+}
+
+// Creates the file when it doesn't exist yet
+fn append(path: String, text: String) -> Result<(), HarpError> {
+  let file = OpenOptions::new().create(true).append(true).open(&path);
+  file.and_then(|mut file| file.write_all(text.as_bytes())).or_else(|err| io_err("fs/append", &path, err))
+}
+
+// The names of the entries of a directory, sorted
+fn list_dir(path: String) -> Result<Vec<String>, HarpError> {
+  let entries = match fs::read_dir(&path) {
+    Ok(entries) => entries,
+    Err(err) => return io_err("fs/list-dir", &path, err),
+  };
+  let mut names = Vec::new();
+  for entry in entries {
+    match entry {
+      Ok(entry) => names.push(entry.file_name().to_string_lossy().into_owned()),
+      Err(err) => return io_err("fs/list-dir", &path, err),
+    }
+  }
+  names.sort();
+  Ok(names)
+}
+
+// Creates the missing parent directories too, an existing directory is fine
+fn mkdir(path: String) -> Result<(), HarpError> {
+  fs::create_dir_all(&path).or_else(|err| io_err("fs/mkdir", &path, err))
+}
+
+// Removes a file or an empty directory, directories with something in them are an error
+fn remove(path: String) -> Result<(), HarpError> {
+  let res = match fs::symlink_metadata(&path) {
+    Ok(meta) if meta.is_dir() => fs::remove_dir(&path),
+    Ok(_) => fs::remove_file(&path),
+    Err(err) => Err(err),
+  };
+  res.or_else(|err| io_err("fs/remove", &path, err))
+}
+
+// Binds every fs/ function, fs/join takes any number of parts so it's a plain native
+pub fn load_fs(env: &mut EnvHead) {
+  let join = Native::new("fs/join", Arity::at_least(1), std_fs_join);
+  env.set("fs/join".to_string(), Value::NativeFunc(join));
+  env.register("fs/read-string", read_string);
+  env.register("fs/read-lines", read_lines);
+  env.register("fs/write-string", write_string);
+  env.register("fs/append", append);
+  env.register("fs/exists?", |path: String| Path::new(&path).exists());
+  env.register("fs/list-dir", list_dir);
+  env.register("fs/mkdir", mkdir);
+  env.register("fs/remove", remove);
+  env.register("fs/normalize", |path: String| path_string(normalize(&path)));
+}
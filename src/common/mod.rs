@@ -1 +1,5 @@
+pub mod format;
+pub mod fs;
 pub mod prelude;
+pub mod random;
+pub mod strings;
@@ -1,71 +1,94 @@
-use std::io::{stdout, Write};
+use std::io::stdout;
+use std::rc::Rc;
 
-use crossterm::{
-  cursor::{DisableBlinking, EnableBlinking, MoveTo, RestorePosition, SavePosition},
-  execute, ExecutableCommand, Result,
-};
+use crossterm::{cursor::MoveTo, ExecutableCommand};
 
-use crate::evaluator::value::{EnvHead, Value};
-use crate::qeval_expr;
-use crate::qeval_value;
-use std::rc::Rc;
+use crate::common::format::{std_format, std_str};
+use crate::common::fs::load_fs;
+use crate::common::random::{
+  load_random, std_rand_choice, std_rand_float, std_rand_int, std_rand_seed, std_rand_shuffle,
+  std_rand_weighted_choice,
+};
+use crate::common::strings::{load_regex, load_strings};
+use crate::evaluator::adt::{define_type, parse_variant, VariantType};
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::collections::{elements, same_kind, List, Map};
+use crate::evaluator::compare::identical;
+use crate::evaluator::lazy::{self, LazySeq};
+use crate::evaluator::generic::{add_method, alias, generic_for, show, type_of};
+use crate::evaluator::matching::{eval_match, parse_clauses};
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::params::Params;
+use crate::evaluator::pattern::Pattern;
+use crate::evaluator::quick_eval::{qapply, qeval_progn, qeval_value};
+use crate::evaluator::record::define_record;
+use crate::evaluator::value::{lookup, EnvHead, Output, Value};
+use crate::harp_err;
+use crate::reader::reader::Reader;
 
-fn std_print(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  for (i, arg) in args.iter().enumerate() {
-    print!("{}", qeval_value(arg.clone(), env));
-    if i < args.len() - 1 {
-      print!("\n");
+fn std_print(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let len = args.len();
+  for (i, arg) in args.into_iter().enumerate() {
+    let text = show(arg, env)?;
+    env.write_out(&text);
+    if i < len - 1 {
+      env.write_out("\n");
     }
   }
-  Value::Unit
+  Ok(Value::Unit)
 }
 
-fn std_print_ln(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  std_print(args, env);
-  print!("\n");
-  Value::Unit
+fn std_print_ln(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  std_print(args, env)?;
+  env.write_out("\n");
+  Ok(Value::Unit)
 }
 
-pub fn std_set_cursor_pos(args: Vec<Value>, env: &mut EnvHead) -> Value {
+// Default method of show, the text print writes for a value
+fn std_show(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  std_doall(args.clone(), env)?;
+  Ok(Value::String(args[0].to_string()))
+}
+
+pub fn std_set_cursor_pos(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
   match &args[..] {
-    [x, y] => match (qeval_value(x.clone(), env), qeval_value(y.clone(), env)) {
-      (Value::Number(xpos), Value::Number(ypos)) => {
-        stdout().execute(MoveTo(xpos as u16, ypos as u16)).unwrap();
+    [Value::Number(xpos), Value::Number(ypos)] => {
+      if let Err(err) = stdout().execute(MoveTo(*xpos as u16, *ypos as u16)) {
+        return harp_err!("Failed to set the cursor position: {}", err);
       }
-      _ => panic!("Expected x and y to be numbers"),
-    },
-    _ => panic!("Expected x and y to be numbers"),
+    }
+    _ => return harp_err!("Expected x and y to be numbers"),
   }
 
-  Value::Unit
+  Ok(Value::Unit)
 }
 
-pub fn std_add(args: Vec<Value>, env: &mut EnvHead) -> Value {
+pub fn std_add(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
   let mut total = 0.0;
   for arg in args {
-    match qeval_value(arg, env) {
+    match arg {
       Value::Number(num) => total += num,
-      _ => panic!("'+' can only be used with numbers"),
+      _ => return harp_err!("'+' can only be used with numbers"),
     }
   }
-  Value::Number(total)
+  Ok(Value::Number(total))
 }
 
-pub fn std_mul(args: Vec<Value>, env: &mut EnvHead) -> Value {
+pub fn std_mul(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
   let mut total = 1.0;
   for arg in args {
-    match qeval_value(arg, env) {
+    match arg {
       Value::Number(num) => total *= num,
-      v => panic!("Mul (*) can only be used with numbers, but got {}", v),
+      v => return harp_err!("Mul (*) can only be used with numbers, but got {}", v),
     }
   }
-  Value::Number(total)
+  Ok(Value::Number(total))
 }
 
-pub fn std_sub(args: Vec<Value>, env: &mut EnvHead) -> Value {
+pub fn std_sub(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
   let mut total = 0.0;
-  for (i, arg) in args.iter().enumerate() {
-    match qeval_value(arg.clone(), env) {
+  for (i, arg) in args.into_iter().enumerate() {
+    match arg {
       Value::Number(num) => {
         if i == 0 {
           total = num
@@ -73,131 +96,888 @@ pub fn std_sub(args: Vec<Value>, env: &mut EnvHead) -> Value {
           total -= num
         }
       }
-      _ => panic!("'-' can only be used with numbers"),
+      _ => return harp_err!("'-' can only be used with numbers"),
+    }
+  }
+  Ok(Value::Number(total))
+}
+
+fn numbers(args: &[Value], form: &str) -> Result<Vec<f64>, HarpError> {
+  let mut numbers = Vec::with_capacity(args.len());
+  for arg in args {
+    match arg {
+      Value::Number(n) => numbers.push(*n),
+      v => return harp_err!("{} expected numbers, but got {}", form, v),
     }
   }
-  Value::Number(total)
+  Ok(numbers)
+}
+
+// (/ x) is 1/x, (/ x y z) divides x by y and then by z
+pub fn std_div(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let numbers = numbers(&args, "/")?;
+  let (mut total, divisors) = match &numbers[..] {
+    [x, divisors @ ..] if !divisors.is_empty() => (*x, divisors),
+    _ => (1.0, &numbers[..]),
+  };
+  for divisor in divisors {
+    if *divisor == 0.0 {
+      return harp_err!("/ can't divide {} by zero", Value::Number(total));
+    }
+    total /= divisor;
+  }
+  Ok(Value::Number(total))
+}
+
+// Integers are numbers without a fraction, exact up to 2^53 and taken up to but not including 2^63. Passing 1.5
+// where one is expected is an error
+pub fn int_arg(value: &Value, form: &str) -> Result<i64, HarpError> {
+  match value {
+    Value::Number(n) if n.fract() == 0.0 && n.abs() < 9.223_372_036_854_776e18 => Ok(*n as i64),
+    v => harp_err!("{} expected an integer, but got {}", form, v),
+  }
+}
+
+fn int_division(args: &[Value], form: &str) -> Result<(i64, i64), HarpError> {
+  match (int_arg(&args[0], form)?, int_arg(&args[1], form)?) {
+    (x, 0) => harp_err!("{} can't divide {} by zero", form, x),
+    pair => Ok(pair),
+  }
+}
+
+// (quot -7 2) is -3 and (rem -7 2) is -1, both round towards zero
+pub fn std_quot(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let (x, y) = int_division(&args, "quot")?;
+  Ok(Value::Number(x.wrapping_div(y) as f64))
+}
+
+pub fn std_rem(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let (x, y) = int_division(&args, "rem")?;
+  Ok(Value::Number(x.wrapping_rem(y) as f64))
+}
+
+// (mod x y) has the sign of y, (mod -7 2) is 1
+pub fn std_mod(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let (x, y) = int_division(&args, "mod")?;
+  let rem = x.wrapping_rem(y);
+  let res = if rem != 0 && (rem < 0) != (y < 0) { rem + y } else { rem };
+  Ok(Value::Number(res as f64))
+}
+
+fn fold_bits(args: &[Value], form: &str, op: fn(i64, i64) -> i64) -> EvalResult {
+  let mut total = int_arg(&args[0], form)?;
+  for arg in &args[1..] {
+    total = op(total, int_arg(arg, form)?);
+  }
+  Ok(Value::Number(total as f64))
+}
+
+pub fn std_bit_and(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  fold_bits(&args, "bit-and", |a, b| a & b)
+}
+
+pub fn std_bit_or(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  fold_bits(&args, "bit-or", |a, b| a | b)
+}
+
+pub fn std_bit_xor(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  fold_bits(&args, "bit-xor", |a, b| a ^ b)
+}
+
+// Negative integers are taken as 64 bit two's complement, (bit-not 0) is -1 and (popcount -1) is 64
+pub fn std_bit_not(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Number(!int_arg(&args[0], "bit-not")? as f64))
+}
+
+pub fn std_popcount(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Number(int_arg(&args[0], "popcount")?.count_ones() as f64))
+}
+
+// (bit-shift-left x n) for n from 0 to 63, shifting right keeps the sign
+fn shift(args: &[Value], form: &str, op: fn(i64, u32) -> i64) -> EvalResult {
+  let x = int_arg(&args[0], form)?;
+  match int_arg(&args[1], form)? {
+    n @ 0..=63 => Ok(Value::Number(op(x, n as u32) as f64)),
+    n => harp_err!("{} expected a shift from 0 to 63, but got {}", form, n),
+  }
+}
+
+pub fn std_bit_shift_left(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  shift(&args, "bit-shift-left", |x, n| x << n)
+}
+
+pub fn std_bit_shift_right(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  shift(&args, "bit-shift-right", |x, n| x >> n)
+}
+
+// (< a b c) holds when every number is less than the next one
+fn compare_chain(args: Vec<Value>, form: &str, holds: fn(f64, f64) -> bool) -> EvalResult {
+  let numbers = numbers(&args, form)?;
+  Ok(Value::Bool(numbers.windows(2).all(|pair| holds(pair[0], pair[1]))))
+}
+
+pub fn std_lt(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  compare_chain(args, "<", |a, b| a < b)
+}
+
+pub fn std_gt(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  compare_chain(args, ">", |a, b| a > b)
+}
+
+pub fn std_le(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  compare_chain(args, "<=", |a, b| a <= b)
+}
+
+pub fn std_ge(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  compare_chain(args, ">=", |a, b| a >= b)
+}
+
+pub fn std_min(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Number(numbers(&args, "min")?.into_iter().fold(f64::INFINITY, f64::min)))
+}
+
+pub fn std_max(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Number(numbers(&args, "max")?.into_iter().fold(f64::NEG_INFINITY, f64::max)))
+}
+
+// (log x) is the natural logarithm, (log x base) in another base
+pub fn std_log(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  match &numbers(&args, "log")?[..] {
+    [x, ..] if *x <= 0.0 => harp_err!("log expected a positive number, but got {}", Value::Number(*x)),
+    [x] => Ok(Value::Number(x.ln())),
+    [_, base] if *base <= 0.0 || *base == 1.0 => harp_err!("log can't use {} as a base", Value::Number(*base)),
+    [x, base] => Ok(Value::Number(x.log(*base))),
+    _ => harp_err!("log expected a number and an optional base"),
+  }
+}
+
+// Arithmetic, comparisons and bit operations, then the f64 methods of the same name, where a non-number argument is
+// an error before they run
+fn load_math(env: &mut EnvHead) {
+  generic(env, "+", Arity::any(), std_add);
+  generic(env, "-", Arity::any(), std_sub);
+  generic(env, "*", Arity::any(), std_mul);
+  generic(env, "/", Arity::at_least(1), std_div);
+  native(env, "quot", Arity::exact(2), std_quot);
+  native(env, "rem", Arity::exact(2), std_rem);
+  native(env, "mod", Arity::exact(2), std_mod);
+  native(env, "bit-and", Arity::at_least(1), std_bit_and);
+  native(env, "bit-or", Arity::at_least(1), std_bit_or);
+  native(env, "bit-xor", Arity::at_least(1), std_bit_xor);
+  native(env, "bit-not", Arity::exact(1), std_bit_not);
+  native(env, "popcount", Arity::exact(1), std_popcount);
+  native(env, "bit-shift-left", Arity::exact(2), std_bit_shift_left);
+  native(env, "bit-shift-right", Arity::exact(2), std_bit_shift_right);
+  native(env, "<", Arity::at_least(1), std_lt);
+  native(env, ">", Arity::at_least(1), std_gt);
+  native(env, "<=", Arity::at_least(1), std_le);
+  native(env, ">=", Arity::at_least(1), std_ge);
+  native(env, "min", Arity::at_least(1), std_min);
+  native(env, "max", Arity::at_least(1), std_max);
+  native(env, "log", Arity::range(1, 2), std_log);
+  env.set("math/pi".to_string(), Value::Number(std::f64::consts::PI));
+  env.set("math/e".to_string(), Value::Number(std::f64::consts::E));
+  env.register("abs", f64::abs);
+  env.register("floor", f64::floor);
+  env.register("ceil", f64::ceil);
+  // Halfway cases round away from zero
+  env.register("round", f64::round);
+  env.register("trunc", f64::trunc);
+  env.register("sqrt", |x: f64| {
+    if x < 0.0 {
+      return harp_err!("sqrt expected a non-negative number, but got {}", Value::Number(x));
+    }
+    Ok(x.sqrt())
+  });
+  env.register("pow", f64::powf);
+  env.register("exp", f64::exp);
+  env.register("sin", f64::sin);
+  env.register("cos", f64::cos);
+  env.register("tan", f64::tan);
+  env.register("asin", f64::asin);
+  env.register("acos", f64::acos);
+  env.register("atan", f64::atan);
+  env.register("atan2", f64::atan2);
 }
 
-pub fn std_eq(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  return args
-    .into_iter()
-    .reduce(|a, b| Value::Bool(qeval_value(a, env) == qeval_value(b, env)))
-    .unwrap()
-    .clone();
+// True when every argument equals the next one, see evaluator/compare.rs
+pub fn std_eq(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Bool(args.windows(2).all(|pair| pair[0] == pair[1])))
 }
 
-pub fn std_not(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  match &qeval_value(args[0].clone(), env) {
-    &Value::Bool(value) => Value::Bool(!value),
-    _ => Value::Bool(false),
+pub fn std_identical(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Bool(args.windows(2).all(|pair| identical(&pair[0], &pair[1]))))
+}
+
+// (= a b ...) for numbers only
+pub fn std_num_eq(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  compare_chain(args, "=", |a, b| a == b)
+}
+
+// (compare a b) is -1, 0 or 1, in the order sorting uses
+pub fn std_compare(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Number(args[0].cmp(&args[1]) as i8 as f64))
+}
+
+pub fn std_not(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  match args.first() {
+    Some(Value::Bool(value)) => Ok(Value::Bool(!value)),
+    Some(_) => Ok(Value::Bool(false)),
+    None => harp_err!("Not expected an argument"),
+  }
+}
+
+pub fn std_quote(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  match &args[..] {
+    [value] => Ok(value.clone()),
+    _ => harp_err!("Quote expected exactly one expression"),
   }
 }
 
-pub fn std_if(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  match qeval_value(args[0].clone(), env) {
+pub fn std_if(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  if args.len() < 2 {
+    return harp_err!("If expected a condition and a consequent");
+  }
+
+  match qeval_value(args[0].clone(), env)? {
     Value::Bool(boolean) => {
       if boolean {
         qeval_value(args[1].clone(), env)
+      } else if args.len() > 2 {
+        qeval_value(args[2].clone(), env)
       } else {
-        if args.len() > 2 {
-          qeval_value(args[2].clone(), env)
-        } else {
-          Value::Unit
-        }
+        Ok(Value::Unit)
       }
     }
-    v => panic!(
+    v => harp_err!(
       "If expected its expression to evaluate to boolean, but got {}",
       v
     ),
   }
 }
 
-pub fn std_set(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  match &args[0] {
-    Value::Atom(name) => {
-      let value = qeval_value(args[1].clone(), env);
+// (try expr (catch e handler...)), interrupts are never caught
+pub fn std_try(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let (expr, name, handler) = match &args[..] {
+    [expr, Value::List(clause)] => match &clause.to_vec()[..] {
+      [Value::Atom(catch), Value::Atom(name), handler @ ..] if catch == "catch" => {
+        (expr.clone(), name.clone(), handler.to_vec())
+      }
+      _ => return harp_err!("Try expected a (catch name body...) clause"),
+    },
+    _ => return harp_err!("Try expected an expression and a (catch name body...) clause"),
+  };
+
+  match qeval_value(expr, env) {
+    Err(HarpError::Interrupted) => Err(HarpError::Interrupted),
+    Err(err) => {
+      let mut scope = env.clone().push();
+      scope.set(name, Value::String(err.message()));
+      qeval_value(Value::Do(handler), &mut scope)
+    }
+    ok => ok,
+  }
+}
+
+pub fn std_error(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let mut message = String::new();
+  for arg in args {
+    message.push_str(&arg.to_string());
+  }
+  Err(HarpError::Runtime(message))
+}
+
+pub fn std_set(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  match &args[..] {
+    [Value::Atom(name), expr] => {
+      let value = qeval_value(expr.clone(), env)?;
+      env.budget().alloc(&value)?;
       env.set(name.to_string(), value.clone());
-      value
+      Ok(value)
     }
-    v => panic!("Set expected an identifier, but got: {}", v),
+    [v, _] => harp_err!("Set expected an identifier, but got: {}", v),
+    _ => harp_err!("Set expected an identifier and a value"),
   }
 }
 
-pub fn std_define(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  match &args[0] {
-    Value::Atom(name) => {
-      let value = qeval_value(args[1].clone(), env);
-      if let Some(_) = env.get(name.to_string()) {
-        panic!("{} is already defined", name);
+pub fn std_define(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  match &args[..] {
+    [Value::Atom(name), expr] => {
+      let value = qeval_value(expr.clone(), env)?;
+      if env.get(name.to_string()).is_some() {
+        harp_err!("{} is already defined", name)
       } else {
+        env.budget().alloc(&value)?;
         env.set(name.to_string(), value.clone());
-        value
+        Ok(value)
       }
     }
-    v => panic!("Def expected an identifier, but got: {}", v),
+    [v, _] => harp_err!("Def expected an identifier, but got: {}", v),
+    _ => harp_err!("Def expected an identifier and a value"),
   }
 }
 
-pub fn std_defun(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  if args.len() < 3 {
-    panic!("Defun expected a list of parameters and a body")
+// (let [pattern expr ...] body...), each binding sees the ones before it
+pub fn std_let(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let mut scope = let_scope(&args, env)?;
+  qeval_value(Value::Do(args[1..].to_vec()), &mut scope)
+}
+
+// The bindings are made in a frame of their own, which is gone by the time the body runs and maybe recurses
+fn let_scope(args: &[Value], env: &EnvHead) -> Result<EnvHead, HarpError> {
+  let bindings = match args.first() {
+    Some(Value::Vector(bindings)) if bindings.len() % 2 == 0 => bindings,
+    Some(Value::Vector(_)) => return harp_err!("Let expected a value for every pattern"),
+    _ => return harp_err!("Let expected a vector of bindings"),
+  };
+
+  let mut scope = env.clone().push();
+  for i in (0..bindings.len()).step_by(2) {
+    let pattern = Pattern::parse(&bindings[i])?;
+    let value = qeval_value(bindings[i + 1].clone(), &mut scope)?;
+    pattern.bind(value, &mut scope)?;
   }
+  Ok(scope)
+}
 
+// (doseq [pattern sequence] body...), maps are walked as [key value] entries
+pub fn std_doseq(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let (pattern, expr) = match args.first() {
+    Some(Value::Vector(binding)) if binding.len() == 2 => (Pattern::parse(&binding[0])?, binding[1].clone()),
+    _ => return harp_err!("Doseq expected a [pattern sequence] binding"),
+  };
+
+  // Lazy sequences are walked as the loop goes
+  let value = qeval_value(expr, env)?;
+  let mut seq = match LazySeq::of(&value) {
+    Ok(seq) => seq,
+    Err(_) => return harp_err!("Doseq can't iterate over {}", value),
+  };
+
+  let body = Value::Do(args[1..].to_vec());
+  while let Some((item, rest)) = seq.force(env)? {
+    env.budget().step()?;
+    let mut scope = env.clone().push();
+    pattern.bind(item, &mut scope)?;
+    qeval_value(body.clone(), &mut scope)?;
+    seq = rest;
+  }
+  Ok(Value::Unit)
+}
+
+// (match expr pattern body ...), see evaluator/matching.rs
+pub fn std_match(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let clauses = parse_clauses(&args[1..])?;
+  let value = qeval_value(args[0].clone(), env)?;
+  eval_match(value, &clauses, env)
+}
+
+// (defrecord Room [name exits]), see evaluator/record.rs
+pub fn std_defrecord(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let (name, fields) = match &args[..] {
+    [Value::Atom(name), fields @ Value::Vector(_)] | [Value::Atom(name), fields @ Value::List(_)] => {
+      (name, elements(fields).unwrap_or_default())
+    }
+    _ => return harp_err!("Defrecord expected a name and a vector of fields"),
+  };
+
+  let mut names = Vec::new();
+  for field in fields {
+    match field {
+      Value::Atom(field) if !names.contains(&field) => names.push(field),
+      v => return harp_err!("Defrecord expected distinct field names, but got {}", v),
+    }
+  }
+  define_record(name, names, env);
+  Ok(Value::Atom(name.clone()))
+}
+
+// (deftype Shape (Circle r) (Rect w h) Empty), see evaluator/adt.rs
+pub fn std_deftype(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let name = match &args[0] {
+    Value::Atom(name) => name,
+    v => return harp_err!("Deftype expected a type name, but got {}", v),
+  };
+
+  let mut variants: Vec<VariantType> = Vec::new();
+  for syntax in &args[1..] {
+    let variant = parse_variant(syntax)?;
+    if variants.iter().any(|v| v.name == variant.name) {
+      return harp_err!("Deftype {} has two variants named {}", name, variant.name);
+    }
+    variants.push(variant);
+  }
+  define_type(name, variants, env);
+  Ok(Value::Atom(name.clone()))
+}
+
+// (get coll key default?) for maps, records and variants
+pub fn std_get(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
   match &args[0] {
-    Value::Atom(name) => {
-      let params = &args[1];
-      let progn: Vec<Value> = args[2..args.len()].to_vec();
-
-      match params {
-        Value::List(ps) => {
-          let mut params_names: Vec<String> = Vec::new();
-          for value in ps {
-            match value {
-              Value::Atom(value) => {
-                params_names.push(value.clone());
-              }
-              v => panic!("Defun expects a list of parameters, got {}", v),
-            }
-          }
-
-          let res = Value::Func(name.to_string(), params_names, Box::new(Value::Do(progn)));
-          env.set(name.to_string(), res.clone());
-          res
+    Value::Map(_) | Value::Record(_) | Value::Variant(_) | Value::Unit => match lookup(&args[0], &args[1]) {
+      Some(value) => Ok(value.clone()),
+      None => Ok(args.get(2).cloned().unwrap_or(Value::Unit)),
+    },
+    v => harp_err!("Get expected a map, a record or a variant, but got {}", v),
+  }
+}
+
+// (assoc coll key value ...) returns a copy with the entries set
+pub fn std_assoc(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  if args.len() % 2 != 1 {
+    return harp_err!("Assoc expected a value for every key");
+  }
+
+  let mut res = args[0].clone();
+  for pair in args[1..].chunks(2) {
+    res = match res {
+      Value::Map(map) => Value::Map(map.update(pair[0].clone(), pair[1].clone())),
+      Value::Unit => Value::Map(Map::default().update(pair[0].clone(), pair[1].clone())),
+      Value::Vector(mut xs) => match &pair[0] {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < xs.len() => {
+          Value::Vector(xs.update(*n as usize, pair[1].clone()))
         }
-        otherwise => panic!(
-          "Defun expected a list of parameters, but got: {}",
-          otherwise
-        ),
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n as usize == xs.len() => {
+          xs.push_back(pair[1].clone());
+          Value::Vector(xs)
+        }
+        v => return harp_err!("Assoc can't set index {} of a vector of {}", v, xs.len()),
+      },
+      Value::Record(record) => Value::Record(record.with(&pair[0], pair[1].clone())?),
+      v => return harp_err!("Assoc expected a map, a vector or a record, but got {}", v),
+    };
+  }
+  Ok(res)
+}
+
+// (cons x list) puts x in front of a list, sharing it as the tail
+pub fn std_cons(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  match &args[1] {
+    Value::List(xs) => Ok(Value::List(xs.cons(args[0].clone()))),
+    Value::Unit => Ok(Value::List(List::new().cons(args[0].clone()))),
+    Value::Lazy(seq) => Ok(Value::Lazy(LazySeq::cons(args[0].clone(), seq.clone()))),
+    v => harp_err!("Cons expected a list or a lazy sequence, but got {}", v),
+  }
+}
+
+// (conj coll x ...) adds where it's cheapest: in front of a list, at the end of a vector, [key value] to a map
+pub fn std_conj(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let mut res = args[0].clone();
+  for x in &args[1..] {
+    res = match (res, x) {
+      (Value::List(xs), x) => Value::List(xs.cons(x.clone())),
+      (Value::Unit, x) => Value::List(List::new().cons(x.clone())),
+      (Value::Vector(mut xs), x) => {
+        xs.push_back(x.clone());
+        Value::Vector(xs)
+      }
+      (Value::Map(map), Value::Vector(entry)) if entry.len() == 2 => {
+        Value::Map(map.update(entry[0].clone(), entry[1].clone()))
       }
+      (Value::Map(_), x) => return harp_err!("Conj expected a [key value] entry for a map, but got {}", x),
+      (v, _) => return harp_err!("Conj expected a list, a vector or a map, but got {}", v),
+    };
+  }
+  Ok(res)
+}
+
+/*
+  Sequences: lists, vectors, strings (as their characters), maps (as [key value] entries), lazy sequences and
+  (). Functions returning part of a sequence keep its kind, map, zip and range make lists. Lazy sequences are
+  walked lazily where evaluator/lazy.rs says so and realized elsewhere. Parts written in Harp are in
+  std/prelude.harp.
+*/
+
+pub fn seq_arg(value: &Value, form: &str, env: &mut EnvHead) -> Result<Vec<Value>, HarpError> {
+  match (value, elements(value)) {
+    (Value::Lazy(seq), _) => seq.to_vec(env),
+    (_, Some(items)) => Ok(items),
+    (_, None) => harp_err!("{} expected a sequence, but got {}", form, value),
+  }
+}
+
+fn lazy_arg(value: &Value, form: &str) -> Result<LazySeq, HarpError> {
+  match LazySeq::of(value) {
+    Ok(seq) => Ok(seq),
+    Err(_) => harp_err!("{} expected a sequence, but got {}", form, value),
+  }
+}
+
+fn count_arg(value: &Value, form: &str) -> Result<usize, HarpError> {
+  match value {
+    Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+    v => harp_err!("{} expected a non-negative integer, but got {}", form, v),
+  }
+}
+
+fn test(pred: &Value, x: Value, form: &str, env: &mut EnvHead) -> Result<bool, HarpError> {
+  match qapply(pred.clone(), vec![x], env)? {
+    Value::Bool(b) => Ok(b),
+    v => harp_err!("{} expected its predicate to return a boolean, but got {}", form, v),
+  }
+}
+
+pub fn std_list(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::list(args))
+}
+
+pub fn std_vector(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::vector(args))
+}
+
+// (range end), (range start end) or (range start end step), end is excluded
+pub fn std_range(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let mut numbers = Vec::new();
+  for arg in &args {
+    match arg {
+      Value::Number(n) => numbers.push(*n),
+      v => return harp_err!("Range expected numbers, but got {}", v),
     }
-    v => panic!("Set expected an identifier, but got: {}", v),
   }
+  let (start, end, step) = match numbers[..] {
+    [end] => (0.0, end, 1.0),
+    [start, end] => (start, end, 1.0),
+    [start, end, step] => (start, end, step),
+    _ => unreachable!("arity is checked"),
+  };
+  if step == 0.0 || step.is_nan() {
+    return harp_err!("Range expected a non-zero step");
+  }
+
+  let mut items = Vec::new();
+  let mut n = start;
+  while (step > 0.0 && n < end) || (step < 0.0 && n > end) {
+    env.budget().step()?;
+    items.push(Value::Number(n));
+    n = start + step * items.len() as f64;
+  }
+  Ok(Value::list(items))
 }
 
-pub fn std_lambda(args: Vec<Value>, env: &mut EnvHead) -> Value {
-  if args.len() < 2 {
-    panic!("Defun expected a list of parameters and a body")
+// (lazy-seq body...), see evaluator/lazy.rs
+pub fn std_lazy_seq(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Lazy(lazy::lazy_body(Value::Do(args), env)))
+}
+
+// (iterate f x) is x, (f x), (f (f x)) ...
+pub fn std_iterate(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Lazy(lazy::iterate(args[0].clone(), args[1].clone())))
+}
+
+// (repeat x) forever or (repeat n x)
+pub fn std_repeat(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  match &args[..] {
+    [x] => Ok(Value::Lazy(lazy::repeat(x.clone()))),
+    [n, x] => Ok(Value::list(vec![x.clone(); count_arg(n, "Repeat")?])),
+    _ => unreachable!("arity is checked"),
   }
+}
 
-  let params = &args[0];
-  let progn: Vec<Value> = args[1..args.len()].to_vec();
+pub fn std_cycle(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let items = seq_arg(&args[0], "Cycle", env)?;
+  Ok(Value::Lazy(lazy::cycle(Rc::new(items), 0)))
+}
 
-  match params {
-    Value::List(ps) => {
-      let mut params_names: Vec<String> = Vec::new();
-      for value in ps {
-        match value {
-          Value::Atom(value) => {
-            params_names.push(value.clone());
-          }
-          v => panic!("Lambda expects a list of parameters, got {}", v),
+// (doall coll) realizes a lazy sequence and returns it
+pub fn std_doall(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  if let Value::Lazy(seq) = &args[0] {
+    seq.to_vec(env)?;
+  }
+  Ok(args[0].clone())
+}
+
+pub fn std_is_empty(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let empty = match &args[0] {
+    Value::Lazy(seq) => seq.force(env)?.is_none(),
+    Value::List(xs) => xs.is_empty(),
+    coll => seq_arg(coll, "Empty?", env)?.is_empty(),
+  };
+  Ok(Value::Bool(empty))
+}
+
+// (first coll), () when empty
+pub fn std_first(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  match &args[0] {
+    Value::List(xs) => Ok(xs.first().cloned().unwrap_or(Value::Unit)),
+    Value::Lazy(seq) => Ok(seq.force(env)?.map(|(x, _)| x).unwrap_or(Value::Unit)),
+    coll => Ok(seq_arg(coll, "First", env)?.into_iter().next().unwrap_or(Value::Unit)),
+  }
+}
+
+// (rest coll), everything after the first element
+pub fn std_rest(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  match &args[0] {
+    Value::List(xs) => Ok(Value::List(xs.rest())),
+    Value::Lazy(seq) => Ok(Value::Lazy(seq.rest())),
+    Value::Vector(xs) => Ok(Value::Vector(xs.skip(1.min(xs.len())))),
+    coll => Ok(same_kind(coll, seq_arg(coll, "Rest", env)?.into_iter().skip(1).collect())),
+  }
+}
+
+// (nth coll index default?)
+pub fn std_nth(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let index = count_arg(&args[1], "Nth")?;
+  let found = match &args[0] {
+    Value::List(xs) => xs.iter().nth(index).cloned(),
+    Value::Vector(xs) => xs.get(index).cloned(),
+    Value::Lazy(seq) => seq.nth(index, env)?,
+    coll => seq_arg(coll, "Nth", env)?.into_iter().nth(index),
+  };
+  match (found, args.get(2)) {
+    (Some(x), _) => Ok(x),
+    (None, Some(default)) => Ok(default.clone()),
+    (None, None) => harp_err!("Nth index {} is out of bounds for {}", index, args[0]),
+  }
+}
+
+pub fn std_length(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let len = match &args[0] {
+    Value::Lazy(seq) => seq.to_vec(env)?.len(),
+    Value::List(xs) => xs.len(),
+    Value::Vector(xs) => xs.len(),
+    Value::Map(map) => map.len(),
+    Value::String(s) => s.chars().count(),
+    Value::Unit => 0,
+    v => return harp_err!("Length expected a sequence, but got {}", v),
+  };
+  Ok(Value::Number(len as f64))
+}
+
+// (map f coll ...), with several sequences f gets an element of each and the shortest one ends it
+pub fn std_map(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  if args[1..].iter().any(|coll| matches!(coll, Value::Lazy(_))) {
+    let seqs = args[1..].iter().map(|coll| lazy_arg(coll, "Map")).collect::<Result<Vec<_>, _>>()?;
+    return Ok(Value::Lazy(lazy::map(args[0].clone(), seqs)));
+  }
+  let seqs = args[1..].iter().map(|coll| seq_arg(coll, "Map", env)).collect::<Result<Vec<_>, _>>()?;
+  let len = seqs.iter().map(Vec::len).min().unwrap_or(0);
+  let mut items = Vec::with_capacity(len);
+  for i in 0..len {
+    let xs = seqs.iter().map(|seq| seq[i].clone()).collect();
+    items.push(qapply(args[0].clone(), xs, env)?);
+  }
+  Ok(Value::list(items))
+}
+
+pub fn std_filter(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  if let Value::Lazy(seq) = &args[1] {
+    return Ok(Value::Lazy(lazy::filter(args[0].clone(), seq.clone())));
+  }
+  let mut items = Vec::new();
+  for x in seq_arg(&args[1], "Filter", env)? {
+    if test(&args[0], x.clone(), "Filter", env)? {
+      items.push(x);
+    }
+  }
+  Ok(same_kind(&args[1], items))
+}
+
+// (reduce f init coll), or (reduce f coll) starting from the first element
+pub fn std_reduce(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let (mut acc, items) = match &args[1..] {
+    [init, coll] => (init.clone(), seq_arg(coll, "Reduce", env)?),
+    [coll] => {
+      let mut items = seq_arg(coll, "Reduce", env)?.into_iter();
+      match items.next() {
+        Some(first) => (first, items.collect()),
+        None => return qapply(args[0].clone(), Vec::new(), env),
+      }
+    }
+    _ => unreachable!("arity is checked"),
+  };
+  for x in items {
+    acc = qapply(args[0].clone(), vec![acc, x], env)?;
+  }
+  Ok(acc)
+}
+
+pub fn std_for_each(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  for x in seq_arg(&args[1], "For-each", env)? {
+    qapply(args[0].clone(), vec![x], env)?;
+  }
+  Ok(Value::Unit)
+}
+
+// A stable merge sort, so errors from the comparator can stop it
+fn merge_sort(
+  mut items: Vec<Value>,
+  before: &mut dyn FnMut(&Value, &Value) -> Result<bool, HarpError>,
+) -> Result<Vec<Value>, HarpError> {
+  if items.len() < 2 {
+    return Ok(items);
+  }
+  let right = merge_sort(items.split_off(items.len() / 2), before)?;
+  let left = merge_sort(items, before)?;
+
+  let mut res = Vec::with_capacity(left.len() + right.len());
+  let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+  while let (Some(x), Some(y)) = (left.peek(), right.peek()) {
+    if before(y, x)? {
+      res.extend(right.next());
+    } else {
+      res.extend(left.next());
+    }
+  }
+  res.extend(left);
+  res.extend(right);
+  Ok(res)
+}
+
+// (sort coll comparator?), the comparator returns whether its first argument goes first or a number like compare
+pub fn std_sort(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let items = seq_arg(&args[0], "Sort", env)?;
+  let sorted = match args.get(1) {
+    None => {
+      let mut items = items;
+      items.sort();
+      items
+    }
+    Some(comparator) => merge_sort(items, &mut |a, b| {
+      match qapply(comparator.clone(), vec![a.clone(), b.clone()], env)? {
+        Value::Bool(before) => Ok(before),
+        Value::Number(n) => Ok(n < 0.0),
+        v => harp_err!("Sort expected its comparator to return a boolean or a number, but got {}", v),
+      }
+    })?,
+  };
+  Ok(same_kind(&args[0], sorted))
+}
+
+// (zip a b ...), a list of [a b] vectors as long as the shortest sequence
+pub fn std_zip(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  if args.iter().any(|coll| matches!(coll, Value::Lazy(_))) {
+    let seqs = args.iter().map(|coll| lazy_arg(coll, "Zip")).collect::<Result<Vec<_>, _>>()?;
+    let vector = Value::NativeFunc(Native::new("vector", Arity::any(), std_vector));
+    return Ok(Value::Lazy(lazy::map(vector, seqs)));
+  }
+  let seqs = args.iter().map(|coll| seq_arg(coll, "Zip", env)).collect::<Result<Vec<_>, _>>()?;
+  let len = seqs.iter().map(Vec::len).min().unwrap_or(0);
+  let items = (0..len).map(|i| Value::vector(seqs.iter().map(|seq| seq[i].clone()).collect())).collect();
+  Ok(Value::list(items))
+}
+
+pub fn std_take(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let n = count_arg(&args[0], "Take")?;
+  match &args[1] {
+    Value::Lazy(seq) => Ok(Value::Lazy(lazy::take(n, seq.clone()))),
+    Value::Vector(xs) => Ok(Value::Vector(xs.take(n.min(xs.len())))),
+    coll => Ok(same_kind(coll, seq_arg(coll, "Take", env)?.into_iter().take(n).collect())),
+  }
+}
+
+pub fn std_drop(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let n = count_arg(&args[0], "Drop")?;
+  match &args[1] {
+    Value::Lazy(seq) => Ok(Value::Lazy(lazy::drop(n, seq.clone()))),
+    Value::List(xs) => Ok(Value::List(xs.skip(n))),
+    Value::Vector(xs) => Ok(Value::Vector(xs.skip(n.min(xs.len())))),
+    coll => Ok(same_kind(coll, seq_arg(coll, "Drop", env)?.into_iter().skip(n).collect())),
+  }
+}
+
+pub fn std_reverse(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let mut items = seq_arg(&args[0], "Reverse", env)?;
+  items.reverse();
+  Ok(same_kind(&args[0], items))
+}
+
+// (append a b ...), a sequence of the first one's kind
+pub fn std_append(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let mut items = Vec::new();
+  for coll in &args {
+    items.extend(seq_arg(coll, "Append", env)?);
+  }
+  match args.first() {
+    Some(first) => Ok(same_kind(first, items)),
+    None => Ok(Value::Unit),
+  }
+}
+
+// (defgeneric name (params)), see evaluator/generic.rs
+pub fn std_defgeneric(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  match &args[..] {
+    [Value::Atom(name), Value::List(ps)] => {
+      let params = Params::parse(&ps.to_vec(), "Defgeneric")?;
+      generic_for(name, params.arity(), env)?;
+      Ok(Value::Atom(name.clone()))
+    }
+    _ => harp_err!("Defgeneric expected a name and a list of parameters"),
+  }
+}
+
+// (defmethod name ((param Type) param ...) body...), typed parameters come before any & marker
+pub fn std_defmethod(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let (name, ps) = match &args[..] {
+    [Value::Atom(name), Value::List(ps), _, ..] => (name, ps.to_vec()),
+    _ => return harp_err!("Defmethod expected a name, a list of parameters and a body"),
+  };
+
+  let mut plain = Vec::with_capacity(ps.len());
+  let mut types = Vec::new();
+  for p in &ps {
+    match p {
+      Value::Atom(marker) if marker.starts_with('&') => break,
+      Value::List(typed) => match &typed.to_vec()[..] {
+        [pattern, Value::Atom(ty)] => {
+          plain.push(pattern.clone());
+          types.push(Some(ty.clone()));
         }
+        _ => return harp_err!("Defmethod expected (param Type), but got {}", p),
+      },
+      p => {
+        plain.push(p.clone());
+        types.push(None);
+      }
+    }
+  }
+  plain.extend(ps[types.len()..].iter().cloned());
+
+  let params = Params::parse(&plain, "Defmethod")?;
+  let func = Value::Func(name.to_string(), params, Rc::new(Value::Do(args[2..].to_vec())));
+  add_method(name, types, func, env)?;
+  Ok(Value::Atom(name.clone()))
+}
+
+// (type-of value), the type name methods dispatch on
+pub fn std_type_of(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Atom(type_of(&args[0])))
+}
+
+pub fn std_defun(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  if args.len() < 3 {
+    return harp_err!("Defun expected a list of parameters and a body");
+  }
+
+  match &args[0] {
+    Value::Atom(name) => match &args[1] {
+      Value::List(ps) => {
+        let params = Params::parse(&ps.to_vec(), "Defun")?;
+        let progn: Vec<Value> = args[2..].to_vec();
+        let res = Value::Func(name.to_string(), params, Rc::new(Value::Do(progn)));
+        env.set(name.to_string(), res.clone());
+        Ok(res)
       }
-      return Value::Func("anon".to_string(), params_names, Box::new(Value::Do(progn)));
+      otherwise => harp_err!(
+        "Defun expected a list of parameters, but got: {}",
+        otherwise
+      ),
+    },
+    v => harp_err!("Set expected an identifier, but got: {}", v),
+  }
+}
+
+pub fn std_lambda(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  if args.len() < 2 {
+    return harp_err!("Defun expected a list of parameters and a body");
+  }
+
+  match &args[0] {
+    Value::List(ps) => {
+      let params = Params::parse(&ps.to_vec(), "Lambda")?;
+      let progn: Vec<Value> = args[1..].to_vec();
+      Ok(Value::Func("anon".to_string(), params, Rc::new(Value::Do(progn))))
     }
-    otherwise => panic!(
+    otherwise => harp_err!(
       "Lambda expected a list of parameters, but got: {}",
       otherwise
     ),
@@ -206,36 +986,136 @@ pub fn std_lambda(args: Vec<Value>, env: &mut EnvHead) -> Value {
 
 pub fn make_std_env() -> EnvHead {
   let mut env = EnvHead::new();
+  load_std(&mut env);
+  env
+}
+
+pub fn make_std_env_with_output(out: Output) -> EnvHead {
+  let mut env = EnvHead::with_output(out);
+  load_std(&mut env);
+  env
+}
+
+// Special forms receive their arguments unevaluated. Translators without an environment use this list
+const SPECIAL_FORMS: &[&str] = &["if", "quote", "try", "def", "set!", "let", "match", "doseq", "defrecord", "deftype", "defgeneric", "defmethod", "lazy-seq", "lambda", "λ", "defun"];
+
+pub fn is_special_form(name: &str) -> bool {
+  SPECIAL_FORMS.contains(&name)
+}
+
+fn native(env: &mut EnvHead, name: &str, arity: Arity, func: fn(Vec<Value>, &mut EnvHead) -> EvalResult) {
+  env.set(name.to_string(), Value::NativeFunc(Native::new(name, arity, func)));
+}
+
+fn special(env: &mut EnvHead, name: &str, arity: Arity, func: fn(Vec<Value>, &mut EnvHead) -> EvalResult) {
+  env.set(name.to_string(), Value::NativeFunc(Native::special(name, arity, func)));
+}
+
+// A native scripts can add methods to, it stays the fallback
+fn generic(env: &mut EnvHead, name: &str, arity: Arity, func: fn(Vec<Value>, &mut EnvHead) -> EvalResult) {
+  native(env, name, arity, func);
+  generic_for(name, Arity::any(), env).expect("natives can be made generic");
+}
+
+fn load_std(env: &mut EnvHead) {
   env.set("*version*".to_string(), Value::String("0.0.0".to_string()));
 
   // IO
-  env.set("print".to_string(), Value::NativeFunc(std_print_ln));
-  env.set("println".to_string(), Value::NativeFunc(std_print_ln));
-  env.set(
-    "io/set-cursor-pos".to_string(),
-    Value::NativeFunc(std_set_cursor_pos),
-  );
+  native(env, "print", Arity::any(), std_print_ln);
+  native(env, "println", Arity::any(), std_print_ln);
+  native(env, "io/set-cursor-pos", Arity::exact(2), std_set_cursor_pos);
+  generic(env, "show", Arity::exact(1), std_show);
 
   // Math
-  env.set("+".to_string(), Value::NativeFunc(std_add));
-  env.set("-".to_string(), Value::NativeFunc(std_sub));
-  env.set("*".to_string(), Value::NativeFunc(std_mul));
+  load_math(env);
 
   // Logic
-  env.set("eq".to_string(), Value::NativeFunc(std_eq));
-  env.set("not".to_string(), Value::NativeFunc(std_not));
-  env.set("if".to_string(), Value::NativeFunc(std_if));
+  generic(env, "eq", Arity::at_least(1), std_eq);
+  alias("equal?", "eq", env).expect("eq is generic");
+  native(env, "eq?", Arity::at_least(1), std_identical);
+  native(env, "=", Arity::at_least(1), std_num_eq);
+  native(env, "compare", Arity::exact(2), std_compare);
+  native(env, "not", Arity::exact(1), std_not);
+  special(env, "if", Arity::range(2, 3), std_if);
+  special(env, "quote", Arity::exact(1), std_quote);
+  special(env, "match", Arity::at_least(1), std_match);
+
+  // Errors
+  special(env, "try", Arity::exact(2), std_try);
+  native(env, "error", Arity::any(), std_error);
 
   // Environment
-  env.set("def".to_string(), Value::NativeFunc(std_define));
-  env.set("set!".to_string(), Value::NativeFunc(std_set));
+  special(env, "def", Arity::exact(2), std_define);
+  special(env, "set!", Arity::exact(2), std_set);
+  special(env, "let", Arity::at_least(1), std_let);
+
+  // Data
+  special(env, "defrecord", Arity::exact(2), std_defrecord);
+  special(env, "deftype", Arity::at_least(2), std_deftype);
+  native(env, "get", Arity::range(2, 3), std_get);
+  native(env, "assoc", Arity::at_least(3), std_assoc);
+  native(env, "cons", Arity::exact(2), std_cons);
+  native(env, "conj", Arity::at_least(1), std_conj);
+  native(env, "type-of", Arity::exact(1), std_type_of);
+
+  // Sequences
+  native(env, "list", Arity::any(), std_list);
+  native(env, "vector", Arity::any(), std_vector);
+  native(env, "range", Arity::range(1, 3), std_range);
+  native(env, "first", Arity::exact(1), std_first);
+  native(env, "car", Arity::exact(1), std_first);
+  native(env, "rest", Arity::exact(1), std_rest);
+  native(env, "cdr", Arity::exact(1), std_rest);
+  native(env, "nth", Arity::range(2, 3), std_nth);
+  native(env, "length", Arity::exact(1), std_length);
+  native(env, "map", Arity::at_least(2), std_map);
+  native(env, "filter", Arity::exact(2), std_filter);
+  native(env, "reduce", Arity::range(2, 3), std_reduce);
+  native(env, "for-each", Arity::exact(2), std_for_each);
+  native(env, "sort", Arity::range(1, 2), std_sort);
+  native(env, "zip", Arity::at_least(1), std_zip);
+  native(env, "take", Arity::exact(2), std_take);
+  native(env, "drop", Arity::exact(2), std_drop);
+  native(env, "reverse", Arity::exact(1), std_reverse);
+  native(env, "empty?", Arity::exact(1), std_is_empty);
+  special(env, "lazy-seq", Arity::any(), std_lazy_seq);
+  native(env, "iterate", Arity::exact(2), std_iterate);
+  native(env, "repeat", Arity::range(1, 2), std_repeat);
+  native(env, "cycle", Arity::exact(1), std_cycle);
+  native(env, "doall", Arity::exact(1), std_doall);
+  native(env, "append", Arity::any(), std_append);
+
+  // Strings
+  native(env, "str", Arity::any(), std_str);
+  native(env, "format", Arity::at_least(1), std_format);
+  load_strings(env);
+
+  // Regular expressions
+  load_regex(env);
+
+  // Files
+  load_fs(env);
+
+  // Random numbers
+  native(env, "rand/seed", Arity::exact(1), std_rand_seed);
+  native(env, "rand/int", Arity::range(1, 3), std_rand_int);
+  native(env, "rand/float", Arity::range(0, 3), std_rand_float);
+  native(env, "rand/choice", Arity::range(1, 2), std_rand_choice);
+  native(env, "rand/shuffle", Arity::range(1, 2), std_rand_shuffle);
+  native(env, "rand/weighted-choice", Arity::range(1, 2), std_rand_weighted_choice);
+  load_random(env);
 
   // Loops
+  special(env, "doseq", Arity::at_least(1), std_doseq);
 
   // Functional
-  env.set("lambda".to_string(), Value::NativeFunc(std_lambda));
-  env.set("λ".to_string(), Value::NativeFunc(std_lambda));
-  env.set("defun".to_string(), Value::NativeFunc(std_defun));
+  special(env, "lambda", Arity::at_least(2), std_lambda);
+  special(env, "λ", Arity::at_least(2), std_lambda);
+  special(env, "defun", Arity::at_least(3), std_defun);
+  special(env, "defgeneric", Arity::exact(2), std_defgeneric);
+  special(env, "defmethod", Arity::at_least(3), std_defmethod);
 
-  return env;
+  // The parts written in Harp
+  let progn = Reader::new(include_str!("../../std/prelude.harp")).next_progn().expect("std/prelude.harp parses");
+  qeval_progn(&progn, env).expect("std/prelude.harp loads");
 }
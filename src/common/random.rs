@@ -0,0 +1,147 @@
+/*
+  The rand/ functions. They draw from a generator shared by the whole environment, which (rand/seed n) or the
+  --seed=N flag makes repeat the same numbers on every run, and with every version of Harp as the generators are
+  ChaCha8 rather than whatever rand's StdRng happens to be. (rand/new seed?) makes an independent generator,
+  every function takes one as an optional first argument: (rand/int g 1 7) leaves the shared generator alone.
+
+    (rand/int hi) (rand/int lo hi)        an integer from lo (or 0) up to but not including hi
+    (rand/float) (rand/float lo hi)       a number in [0, 1) or [lo, hi)
+    (rand/choice coll)                    an element of a non-empty sequence
+    (rand/shuffle coll)                   the elements in a random order, a vector stays a vector
+    (rand/weighted-choice entries)        an item from {item weight} or [[item weight] ...]
+*/
+
+use std::rc::Rc;
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::common::prelude::{int_arg, seq_arg};
+use crate::evaluator::collections::same_kind;
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::userdata::{UserData, UserType};
+use crate::evaluator::value::{EnvHead, Value};
+use crate::harp_err;
+
+fn number_arg(value: &Value, form: &str) -> Result<f64, HarpError> {
+  match value {
+    Value::Number(n) => Ok(*n),
+    v => harp_err!("{} expected a number, but got {}", form, v),
+  }
+}
+
+// Runs f with the generator given as the first argument, or the shared one, and the remaining arguments
+fn with_generator<F>(args: &[Value], env: &EnvHead, f: F) -> EvalResult
+where
+  F: FnOnce(&mut ChaCha8Rng, &[Value]) -> EvalResult,
+{
+  match args.first() {
+    Some(Value::UserData(data)) if data.is::<ChaCha8Rng>() => {
+      f(&mut *data.borrow_mut::<ChaCha8Rng>()?, &args[1..])
+    }
+    _ => f(&mut env.random().borrow_mut(), args),
+  }
+}
+
+// The functions taking a collection last have nothing but a generator before it
+fn no_more(rest: &[Value], form: &str) -> Result<(), HarpError> {
+  match rest.first() {
+    Some(v) => harp_err!("{} expected a generator, but got {}", form, v),
+    None => Ok(()),
+  }
+}
+
+pub fn std_rand_seed(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  env.seed_random(int_arg(&args[0], "rand/seed")? as u64);
+  Ok(Value::Unit)
+}
+
+pub fn std_rand_int(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  with_generator(&args, env, |rng, args| {
+    let (lo, hi) = match args {
+      [hi] => (0, int_arg(hi, "rand/int")?),
+      [lo, hi] => (int_arg(lo, "rand/int")?, int_arg(hi, "rand/int")?),
+      _ => return harp_err!("rand/int expected an upper bound or a range"),
+    };
+    if lo >= hi {
+      return harp_err!("rand/int expected a non-empty range, but got {}..{}", lo, hi);
+    }
+    Ok(Value::Number(rng.gen_range(lo..hi) as f64))
+  })
+}
+
+pub fn std_rand_float(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  with_generator(&args, env, |rng, args| {
+    let (lo, hi) = match args {
+      [] => (0.0, 1.0),
+      [lo, hi] => (number_arg(lo, "rand/float")?, number_arg(hi, "rand/float")?),
+      _ => return harp_err!("rand/float expected no bounds or a range"),
+    };
+    if lo >= hi || !(hi - lo).is_finite() {
+      let (lo, hi) = (Value::Number(lo), Value::Number(hi));
+      return harp_err!("rand/float expected a non-empty range, but got {}..{}", lo, hi);
+    }
+    Ok(Value::Number(rng.gen_range(lo..hi)))
+  })
+}
+
+pub fn std_rand_choice(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let items = seq_arg(args.last().unwrap(), "rand/choice", env)?;
+  with_generator(&args[..args.len() - 1], env, |rng, rest| {
+    no_more(rest, "rand/choice")?;
+    match items.choose(rng) {
+      Some(item) => Ok(item.clone()),
+      None => harp_err!("rand/choice expected a non-empty sequence"),
+    }
+  })
+}
+
+pub fn std_rand_shuffle(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let coll = args.last().unwrap();
+  let mut items = seq_arg(coll, "rand/shuffle", env)?;
+  with_generator(&args[..args.len() - 1], env, |rng, rest| {
+    no_more(rest, "rand/shuffle")?;
+    items.shuffle(rng);
+    Ok(same_kind(coll, items))
+  })
+}
+
+pub fn std_rand_weighted_choice(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let mut items = Vec::new();
+  let mut weights = Vec::new();
+  for entry in seq_arg(args.last().unwrap(), "rand/weighted-choice", env)? {
+    match entry {
+      Value::Vector(pair) if pair.len() == 2 => match &pair[1] {
+        Value::Number(weight) if *weight >= 0.0 && weight.is_finite() => {
+          items.push(pair[0].clone());
+          weights.push(*weight);
+        }
+        v => return harp_err!("rand/weighted-choice expected a non-negative weight, but got {}", v),
+      },
+      v => return harp_err!("rand/weighted-choice expected an [item weight] entry, but got {}", v),
+    }
+  }
+  let index = match WeightedIndex::new(&weights) {
+    Ok(index) => index,
+    Err(_) => return harp_err!("rand/weighted-choice expected some weight above zero"),
+  };
+  with_generator(&args[..args.len() - 1], env, |rng, rest| {
+    no_more(rest, "rand/weighted-choice")?;
+    Ok(items[index.sample(rng)].clone())
+  })
+}
+
+// rand/new closes over the Random type its generators belong to
+pub fn load_random(env: &mut EnvHead) {
+  let ty = env.register_type(UserType::new("Random"));
+  let new = Native::new("rand/new", Arity::range(0, 1), move |args, _env| {
+    let rng = match args.first() {
+      Some(seed) => ChaCha8Rng::seed_from_u64(int_arg(seed, "rand/new")? as u64),
+      None => ChaCha8Rng::from_entropy(),
+    };
+    Ok(Value::UserData(UserData::new(&Rc::clone(&ty), rng)))
+  });
+  env.set("rand/new".to_string(), Value::NativeFunc(new));
+}
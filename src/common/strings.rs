@@ -0,0 +1,149 @@
+/*
+  The str/ and re/ functions. Positions and lengths count characters, not bytes, so (str/substring "héllo" 1 3)
+  is "él" and (str/index-of "héllo" "l") is 2. Searching for something that isn't there gives () rather than an
+  error.
+*/
+
+use crate::common::prelude::seq_arg;
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::re::Re;
+use crate::evaluator::value::{EnvHead, Value};
+use crate::harp_err;
+
+fn string_arg<'a>(value: &'a Value, form: &str) -> Result<&'a str, HarpError> {
+  match value {
+    Value::String(s) => Ok(s),
+    v => harp_err!("{} expected a string, but got {}", form, v),
+  }
+}
+
+fn char_index(s: &str, byte: usize) -> usize {
+  s[..byte].chars().count()
+}
+
+// The byte offset of a character position, None past the end
+fn byte_offset(s: &str, index: usize) -> Option<usize> {
+  s.char_indices().map(|(i, _)| i).chain(std::iter::once(s.len())).nth(index)
+}
+
+// (str/split s) on whitespace, (str/split s sep) on sep, "" splits into characters
+fn std_str_split(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let s = string_arg(&args[0], "str/split")?;
+  let parts: Vec<&str> = match args.get(1) {
+    None => s.split_whitespace().collect(),
+    Some(sep) => match string_arg(sep, "str/split")? {
+      "" => s.char_indices().map(|(i, c)| &s[i..i + c.len_utf8()]).collect(),
+      sep => s.split(sep).collect(),
+    },
+  };
+  Ok(Value::list(parts.into_iter().map(|part| Value::String(part.to_string())).collect()))
+}
+
+// (str/join coll sep?), strings go in as they are and other values as they print
+fn std_str_join(args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+  let sep = match args.get(1) {
+    Some(sep) => string_arg(sep, "str/join")?.to_string(),
+    None => String::new(),
+  };
+  let parts: Vec<String> = seq_arg(&args[0], "str/join", env)?.iter().map(Value::to_string).collect();
+  Ok(Value::String(parts.join(&sep)))
+}
+
+// (str/substring s start end?), end defaults to the end of the string
+fn std_str_substring(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let s = string_arg(&args[0], "str/substring")?;
+  let mut bounds = Vec::new();
+  for arg in &args[1..] {
+    match arg {
+      Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => bounds.push(*n as usize),
+      v => return harp_err!("str/substring expected a non-negative integer, but got {}", v),
+    }
+  }
+  let len = s.chars().count();
+  let (start, end) = (bounds[0], bounds.get(1).copied().unwrap_or(len));
+  match (byte_offset(s, start), byte_offset(s, end)) {
+    (Some(from), Some(to)) if start <= end => Ok(Value::String(s[from..to].to_string())),
+    _ => harp_err!("str/substring range {}..{} is out of bounds for a string of {} characters", start, end, len),
+  }
+}
+
+// Regular expressions, see evaluator/re.rs. Patterns can also be given as strings
+
+fn regex_arg(value: &Value, form: &str) -> Result<Re, HarpError> {
+  match value {
+    Value::Regex(re) => Ok(re.clone()),
+    Value::String(pattern) => Re::new(pattern).map_err(|err| HarpError::Runtime(format!("{} {}", form, err))),
+    v => harp_err!("{} expected a regular expression, but got {}", form, v),
+  }
+}
+
+fn std_re_compile(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  Ok(Value::Regex(regex_arg(&args[0], "re/compile")?))
+}
+
+// (re/match re s), the match of the whole string or ()
+fn std_re_match(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let re = regex_arg(&args[0], "re/match")?;
+  Ok(re.matches(string_arg(&args[1], "re/match")?))
+}
+
+// (re/find re s), the first match anywhere in the string or ()
+fn std_re_find(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let re = regex_arg(&args[0], "re/find")?;
+  Ok(re.find(string_arg(&args[1], "re/find")?))
+}
+
+fn std_re_find_all(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let re = regex_arg(&args[0], "re/find-all")?;
+  Ok(Value::list(re.find_all(string_arg(&args[1], "re/find-all")?)))
+}
+
+// (re/replace re s replacement), every match, $1 and ${name} in the replacement stand for groups
+fn std_re_replace(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let re = regex_arg(&args[0], "re/replace")?;
+  let s = string_arg(&args[1], "re/replace")?;
+  let replacement = string_arg(&args[2], "re/replace")?;
+  Ok(Value::String(re.regex().replace_all(s, replacement).into_owned()))
+}
+
+fn std_re_split(args: Vec<Value>, _env: &mut EnvHead) -> EvalResult {
+  let re = regex_arg(&args[0], "re/split")?;
+  let parts = re.regex().split(string_arg(&args[1], "re/split")?);
+  Ok(Value::list(parts.map(|part| Value::String(part.to_string())).collect()))
+}
+
+fn native(env: &mut EnvHead, name: &str, arity: Arity, func: fn(Vec<Value>, &mut EnvHead) -> EvalResult) {
+  env.set(name.to_string(), Value::NativeFunc(Native::new(name, arity, func)));
+}
+
+pub fn load_regex(env: &mut EnvHead) {
+  native(env, "re/compile", Arity::exact(1), std_re_compile);
+  native(env, "re/match", Arity::exact(2), std_re_match);
+  native(env, "re/find", Arity::exact(2), std_re_find);
+  native(env, "re/find-all", Arity::exact(2), std_re_find_all);
+  native(env, "re/replace", Arity::exact(3), std_re_replace);
+  native(env, "re/split", Arity::exact(2), std_re_split);
+}
+
+// Every str/ function. The ones with optional arguments take values, the rest are registered over plain Rust
+// strings and str/index-of counts characters as str/substring does
+pub fn load_strings(env: &mut EnvHead) {
+  native(env, "str/split", Arity::range(1, 2), std_str_split);
+  native(env, "str/join", Arity::range(1, 2), std_str_join);
+  native(env, "str/substring", Arity::range(2, 3), std_str_substring);
+  env.register("str/length", |s: String| s.chars().count());
+  env.register("str/trim", |s: String| s.trim().to_string());
+  env.register("str/trim-start", |s: String| s.trim_start().to_string());
+  env.register("str/trim-end", |s: String| s.trim_end().to_string());
+  env.register("str/upper", |s: String| s.to_uppercase());
+  env.register("str/lower", |s: String| s.to_lowercase());
+  env.register("str/contains?", |s: String, part: String| s.contains(&part));
+  env.register("str/starts-with?", |s: String, prefix: String| s.starts_with(&prefix));
+  env.register("str/ends-with?", |s: String, suffix: String| s.ends_with(&suffix));
+  env.register("str/index-of", |s: String, part: String| s.find(&part).map(|i| char_index(&s, i)));
+  env.register("str/replace", |s: String, from: String, to: String| s.replace(&from, &to));
+  // () when the string isn't a number
+  env.register("str/to-number", |s: String| s.trim().parse::<f64>().ok());
+  env.register("str/from-number", |n: f64| Value::Number(n).to_string());
+}
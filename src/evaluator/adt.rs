@@ -0,0 +1,160 @@
+/*
+  Algebraic data types, a named type with a fixed set of variants made by deftype:
+
+    (deftype Shape (Circle r) (Rect w h) Empty)
+
+  binds the constructors (Circle 2) and (Rect 1 3), the value Empty for a variant without fields, and the
+  predicates shape?, circle?, rect? and empty?. Variant names start with an uppercase letter so match can
+  tell them apart from names to bind:
+
+    (match shape
+      (Circle r) (* 3 r r)
+      (Rect w h) (* w h)
+      Empty 0)
+
+  Variants print as Circle(2), are equal when they have the same type, variant and equal fields, and
+  their fields can be read with (get shape :r). Every deftype makes a new type, like defrecord.
+*/
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::evaluator::error::HarpError;
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+use crate::harp_err;
+
+pub struct VariantType {
+  pub name: String,
+  pub fields: Vec<String>,
+}
+
+pub struct SumType {
+  pub name: String,
+  pub variants: Vec<VariantType>,
+}
+
+#[derive(Clone)]
+pub struct Variant {
+  pub ty: Rc<SumType>,
+  pub tag: usize,
+  pub fields: Vec<Value>,
+}
+
+// Circle is a variant name, circle is a name to bind
+pub fn is_variant_name(name: &str) -> bool {
+  name.starts_with(|c: char| c.is_uppercase())
+}
+
+impl Variant {
+  pub fn name(&self) -> &str {
+    &self.ty.variants[self.tag].name
+  }
+
+  pub fn get(&self, key: &Value) -> Option<&Value> {
+    let field = match key {
+      Value::Atom(key) if is_keyword(key) => &key[1..],
+      _ => return None,
+    };
+    let index = self.ty.variants[self.tag].fields.iter().position(|f| f == field)?;
+    self.fields.get(index)
+  }
+
+  pub fn is(&self, ty: &Rc<SumType>) -> bool {
+    Rc::ptr_eq(&self.ty, ty)
+  }
+}
+
+impl PartialEq for Variant {
+  fn eq(&self, other: &Variant) -> bool {
+    self.is(&other.ty) && self.tag == other.tag && self.fields == other.fields
+  }
+}
+
+impl fmt::Display for Variant {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())?;
+    if self.fields.is_empty() {
+      return Ok(());
+    }
+    write!(f, "(")?;
+    for (i, field) in self.fields.iter().enumerate() {
+      if i > 0 {
+        write!(f, " ")?;
+      }
+      write!(f, "{}", field)?;
+    }
+    write!(f, ")")
+  }
+}
+
+fn predicate(name: String, test: impl Fn(&Variant) -> bool + 'static) -> Value {
+  Value::NativeFunc(Native::new(&name, Arity::exact(1), move |args, _env| {
+    Ok(Value::Bool(matches!(&args[0], Value::Variant(v) if test(v))))
+  }))
+}
+
+// Binds the constructors and predicates of a new type
+pub fn define_type(name: &str, variants: Vec<VariantType>, env: &mut EnvHead) -> Rc<SumType> {
+  let ty = Rc::new(SumType {
+    name: name.to_string(),
+    variants,
+  });
+
+  let owner = ty.clone();
+  let is = format!("{}?", name.to_lowercase());
+  env.set(is.clone(), predicate(is, move |v| v.is(&owner)));
+
+  for (tag, variant) in ty.variants.iter().enumerate() {
+    let owner = ty.clone();
+    let is = format!("{}?", variant.name.to_lowercase());
+    env.set(is.clone(), predicate(is, move |v| v.is(&owner) && v.tag == tag));
+
+    let owner = ty.clone();
+    let constructor = if variant.fields.is_empty() {
+      Value::Variant(Variant {
+        ty: owner,
+        tag,
+        fields: Vec::new(),
+      })
+    } else {
+      Value::NativeFunc(Native::new(&variant.name, Arity::exact(variant.fields.len()), move |args, _env| {
+        Ok(Value::Variant(Variant {
+          ty: owner.clone(),
+          tag,
+          fields: args,
+        }))
+      }))
+    };
+    env.set(variant.name.clone(), constructor);
+  }
+
+  ty
+}
+
+// (Circle r) or Empty in a deftype
+pub fn parse_variant(value: &Value) -> Result<VariantType, HarpError> {
+  let (name, fields) = match value {
+    Value::Atom(name) => (name.clone(), Vec::new()),
+    Value::List(xs) => match xs.first() {
+      Some(Value::Atom(name)) => (name.clone(), xs.rest().to_vec()),
+      _ => return harp_err!("Deftype expected a variant name in {}", value),
+    },
+    v => return harp_err!("Deftype expected a variant, but got {}", v),
+  };
+  if !is_variant_name(&name) {
+    return harp_err!("Deftype expected variant names to start with an uppercase letter, but got {}", name);
+  }
+
+  let mut names = Vec::new();
+  for field in fields {
+    match field {
+      Value::Atom(field) if !names.contains(&field) => names.push(field),
+      v => return harp_err!("Deftype expected distinct field names, but got {}", v),
+    }
+  }
+  Ok(VariantType {
+    name,
+    fields: names,
+  })
+}
@@ -0,0 +1,231 @@
+/*
+  Persistent collections backing lists, vectors and maps. Copies share their structure, so passing a collection
+  around or looking it up in the environment doesn't copy its elements.
+
+    List     cons cells with shared tails, cons and rest are constant time
+    Vector   an RRB tree (im-rc), indexing, conj and update are logarithmic
+    Map      a hash array mapped trie (im-rc), assoc and get are logarithmic
+
+  Maps iterate in hash order, which doesn't depend on the order the entries were added. Printing and doseq go
+  through sorted_entries so scripts see the same order on every run.
+*/
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+use crate::evaluator::value::Value;
+
+pub type Vector = im_rc::Vector<Value>;
+
+// A fixed hasher, so iteration order is the same on every run
+pub type Map = im_rc::HashMap<Value, Value, BuildHasherDefault<DefaultHasher>>;
+
+struct Cell {
+  value: Value,
+  next: Option<Rc<Cell>>,
+}
+
+#[derive(Clone, Default)]
+pub struct List {
+  head: Option<Rc<Cell>>,
+  len: usize,
+}
+
+impl List {
+  pub fn new() -> List {
+    List::default()
+  }
+
+  // A list starting with value, sharing this one as its tail
+  pub fn cons(&self, value: Value) -> List {
+    List {
+      head: Some(Rc::new(Cell {
+        value,
+        next: self.head.clone(),
+      })),
+      len: self.len + 1,
+    }
+  }
+
+  pub fn first(&self) -> Option<&Value> {
+    self.head.as_ref().map(|cell| &cell.value)
+  }
+
+  // Everything after the first element, the empty list stays empty
+  pub fn rest(&self) -> List {
+    match &self.head {
+      Some(cell) => List {
+        head: cell.next.clone(),
+        len: self.len - 1,
+      },
+      None => List::new(),
+    }
+  }
+
+  // The list without its first n elements
+  pub fn skip(&self, n: usize) -> List {
+    let mut list = self.clone();
+    for _ in 0..n.min(self.len) {
+      list = list.rest();
+    }
+    list
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn iter(&self) -> Iter<'_> {
+    Iter {
+      next: self.head.as_deref(),
+      len: self.len,
+    }
+  }
+
+  // The elements in a vec, to match list syntax against slice patterns
+  pub fn to_vec(&self) -> Vec<Value> {
+    self.iter().cloned().collect()
+  }
+}
+
+// Long lists are dropped cell by cell instead of recursively
+impl Drop for List {
+  fn drop(&mut self) {
+    let mut next = self.head.take();
+    while let Some(cell) = next {
+      match Rc::try_unwrap(cell) {
+        Ok(mut cell) => next = cell.next.take(),
+        Err(_) => break,
+      }
+    }
+  }
+}
+
+pub struct Iter<'a> {
+  next: Option<&'a Cell>,
+  len: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = &'a Value;
+
+  fn next(&mut self) -> Option<&'a Value> {
+    let cell = self.next?;
+    self.next = cell.next.as_deref();
+    self.len -= 1;
+    Some(&cell.value)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a List {
+  type Item = &'a Value;
+  type IntoIter = Iter<'a>;
+
+  fn into_iter(self) -> Iter<'a> {
+    self.iter()
+  }
+}
+
+impl FromIterator<Value> for List {
+  fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> List {
+    let values: Vec<Value> = iter.into_iter().collect();
+    values.into_iter().rev().fold(List::new(), |list, value| list.cons(value))
+  }
+}
+
+impl From<Vec<Value>> for List {
+  fn from(values: Vec<Value>) -> List {
+    values.into_iter().collect()
+  }
+}
+
+impl PartialEq for List {
+  fn eq(&self, other: &List) -> bool {
+    self.len == other.len && self.iter().eq(other.iter())
+  }
+}
+
+impl Eq for List {}
+
+impl PartialOrd for List {
+  fn partial_cmp(&self, other: &List) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for List {
+  fn cmp(&self, other: &List) -> std::cmp::Ordering {
+    self.iter().cmp(other.iter())
+  }
+}
+
+impl Hash for List {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.len.hash(state);
+    for value in self {
+      value.hash(state);
+    }
+  }
+}
+
+impl fmt::Debug for List {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+// Entries ordered by key
+pub fn sorted_entries(map: &Map) -> Vec<(&Value, &Value)> {
+  let mut entries: Vec<(&Value, &Value)> = map.iter().collect();
+  entries.sort();
+  entries
+}
+
+// The elements of a sequence, maps give their [key value] entries in key order, strings their characters and
+// () is empty
+pub fn elements(value: &Value) -> Option<Vec<Value>> {
+  match value {
+    Value::String(s) => Some(s.chars().map(|c| Value::String(c.to_string())).collect()),
+    Value::List(xs) => Some(xs.to_vec()),
+    Value::Vector(xs) => Some(xs.iter().cloned().collect()),
+    Value::Map(map) => Some(
+      sorted_entries(map)
+        .into_iter()
+        .map(|(k, v)| Value::vector(vec![k.clone(), v.clone()]))
+        .collect(),
+    ),
+    Value::Unit => Some(Vec::new()),
+    _ => None,
+  }
+}
+
+// A sequence of the same kind as like holding items, strings stay strings while the items are all strings and
+// anything else becomes a list
+pub fn same_kind(like: &Value, items: Vec<Value>) -> Value {
+  match like {
+    Value::Vector(_) => Value::vector(items),
+    Value::String(_) if items.iter().all(|x| matches!(x, Value::String(_))) => {
+      let mut s = String::new();
+      for x in &items {
+        if let Value::String(x) = x {
+          s.push_str(x);
+        }
+      }
+      Value::String(s)
+    }
+    _ => Value::list(items),
+  }
+}
@@ -0,0 +1,172 @@
+/*
+  Equality, ordering and hashing of values.
+
+  Values are equal when they have the same structure: lists, vectors and maps compare their elements (maps
+  regardless of the order of their entries), records and variants their type and fields, regular expressions
+  their pattern, and NaN equals itself so that every value equals itself. Functions and lazy sequences, which
+  may never end, are equal only to themselves, host values use their type's equality. The ordering sorts values
+  of different kinds by kind (unit, booleans, numbers, strings, atoms, lists, vectors, maps, regular
+  expressions, records, variants, host values, lazy sequences, functions) and values of the same kind by their
+  contents.
+
+  The prelude exposes three equalities: (equal? a b) is this structural equality and can be given methods like
+  eq, (eq? a b) doesn't look through host values or methods, comparing them by identity, and (= a b) compares
+  numbers.
+*/
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::evaluator::collections::{sorted_entries, Map};
+use crate::evaluator::value::Value;
+
+// Kinds in the order they sort in
+fn rank(value: &Value) -> u8 {
+  match value {
+    Value::Unit => 0,
+    Value::Bool(_) => 1,
+    Value::Number(_) => 2,
+    Value::String(_) => 3,
+    Value::Atom(_) => 4,
+    Value::List(_) => 5,
+    Value::Vector(_) => 6,
+    Value::Map(_) => 7,
+    Value::Regex(_) => 8,
+    Value::Record(_) => 9,
+    Value::Variant(_) => 10,
+    Value::UserData(_) => 11,
+    Value::Lazy(_) => 12,
+    Value::NativeFunc(_) | Value::Func(_, _, _) => 13,
+    Value::Do(_) => 14,
+  }
+}
+
+fn compare_numbers(a: f64, b: f64) -> Ordering {
+  a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+fn same_entries(a: &Map, b: &Map, eq: fn(&Value, &Value) -> bool) -> bool {
+  a.len() == b.len() && a.iter().all(|(key, x)| b.get(key).is_some_and(|y| eq(x, y)))
+}
+
+fn same_items<'a>(
+  a: impl ExactSizeIterator<Item = &'a Value>,
+  b: impl ExactSizeIterator<Item = &'a Value>,
+  eq: fn(&Value, &Value) -> bool,
+) -> bool {
+  a.len() == b.len() && a.zip(b).all(|(x, y)| eq(x, y))
+}
+
+fn func_id(progn: &Rc<Value>) -> usize {
+  Rc::as_ptr(progn) as usize
+}
+
+impl PartialEq for Value {
+  fn eq(&self, other: &Value) -> bool {
+    match (self, other) {
+      (Value::Unit, Value::Unit) => true,
+      (Value::Number(a), Value::Number(b)) => compare_numbers(*a, *b) == Ordering::Equal,
+      (Value::String(a), Value::String(b)) | (Value::Atom(a), Value::Atom(b)) => a == b,
+      (Value::Bool(a), Value::Bool(b)) => a == b,
+      (Value::List(a), Value::List(b)) => a == b,
+      (Value::Vector(a), Value::Vector(b)) => a == b,
+      (Value::Do(a), Value::Do(b)) => a == b,
+      (Value::Map(a), Value::Map(b)) => same_entries(a, b, Value::eq),
+      (Value::Record(a), Value::Record(b)) => a == b,
+      (Value::Variant(a), Value::Variant(b)) => a == b,
+      (Value::UserData(a), Value::UserData(b)) => a == b,
+      (Value::NativeFunc(a), Value::NativeFunc(b)) => a.ptr_eq(b),
+      (Value::Func(_, _, a), Value::Func(_, _, b)) => Rc::ptr_eq(a, b),
+      (Value::Lazy(a), Value::Lazy(b)) => a.ptr_eq(b),
+      (Value::Regex(a), Value::Regex(b)) => a == b,
+      _ => false,
+    }
+  }
+}
+
+impl Eq for Value {}
+
+impl Ord for Value {
+  fn cmp(&self, other: &Value) -> Ordering {
+    match (self, other) {
+      (Value::Number(a), Value::Number(b)) => compare_numbers(*a, *b),
+      (Value::String(a), Value::String(b)) | (Value::Atom(a), Value::Atom(b)) => a.cmp(b),
+      (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+      (Value::List(a), Value::List(b)) => a.cmp(b),
+      (Value::Vector(a), Value::Vector(b)) => a.cmp(b),
+      (Value::Do(a), Value::Do(b)) => a.cmp(b),
+      (Value::Map(a), Value::Map(b)) => sorted_entries(a).cmp(&sorted_entries(b)),
+      (Value::Record(a), Value::Record(b)) => (a.ty.name.as_str(), Rc::as_ptr(&a.ty), &a.fields)
+        .cmp(&(b.ty.name.as_str(), Rc::as_ptr(&b.ty), &b.fields)),
+      (Value::Variant(a), Value::Variant(b)) => (a.ty.name.as_str(), Rc::as_ptr(&a.ty), a.tag, &a.fields)
+        .cmp(&(b.ty.name.as_str(), Rc::as_ptr(&b.ty), b.tag, &b.fields)),
+      (Value::UserData(a), Value::UserData(b)) if a == b => Ordering::Equal,
+      (Value::UserData(a), Value::UserData(b)) => (a.type_name(), a.id()).cmp(&(b.type_name(), b.id())),
+      (Value::Lazy(a), Value::Lazy(b)) => a.id().cmp(&b.id()),
+      (Value::Regex(a), Value::Regex(b)) => a.as_str().cmp(b.as_str()),
+      (Value::NativeFunc(a), Value::NativeFunc(b)) => (&a.name, a.id()).cmp(&(&b.name, b.id())),
+      (Value::Func(a, _, x), Value::Func(b, _, y)) => (a, func_id(x)).cmp(&(b, func_id(y))),
+      (Value::NativeFunc(_), Value::Func(_, _, _)) => Ordering::Less,
+      (Value::Func(_, _, _), Value::NativeFunc(_)) => Ordering::Greater,
+      _ => rank(self).cmp(&rank(other)),
+    }
+  }
+}
+
+impl PartialOrd for Value {
+  fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Hash for Value {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    rank(self).hash(state);
+    match self {
+      Value::Unit => {}
+      // 0 and -0 are equal, as are all NaNs
+      Value::Number(n) if *n == 0.0 => 0u64.hash(state),
+      Value::Number(n) if n.is_nan() => f64::NAN.to_bits().hash(state),
+      Value::Number(n) => n.to_bits().hash(state),
+      Value::String(s) | Value::Atom(s) => s.hash(state),
+      Value::Bool(b) => b.hash(state),
+      Value::List(xs) => xs.hash(state),
+      Value::Vector(xs) => xs.hash(state),
+      Value::Do(xs) => xs.hash(state),
+      // Entries in any order hash the same
+      Value::Map(entries) => {
+        let sum = entries.iter().fold(0u64, |sum, entry| {
+          let mut hasher = DefaultHasher::new();
+          entry.hash(&mut hasher);
+          sum.wrapping_add(hasher.finish())
+        });
+        (entries.len(), sum).hash(state)
+      }
+      Value::Record(record) => (Rc::as_ptr(&record.ty), &record.fields).hash(state),
+      Value::Variant(variant) => (Rc::as_ptr(&variant.ty), variant.tag, &variant.fields).hash(state),
+      // Host types may have their own equality, so only the type is hashed
+      Value::UserData(data) => data.type_name().hash(state),
+      Value::NativeFunc(native) => native.id().hash(state),
+      Value::Func(_, _, progn) => func_id(progn).hash(state),
+      Value::Lazy(seq) => seq.id().hash(state),
+      Value::Regex(re) => re.as_str().hash(state),
+    }
+  }
+}
+
+// eq?, equality without host type equality, host values are only identical to themselves
+pub fn identical(a: &Value, b: &Value) -> bool {
+  match (a, b) {
+    (Value::UserData(a), Value::UserData(b)) => a.ptr_eq(b),
+    (Value::List(a), Value::List(b)) => same_items(a.iter(), b.iter(), identical),
+    (Value::Vector(a), Value::Vector(b)) => same_items(a.iter(), b.iter(), identical),
+    (Value::Map(a), Value::Map(b)) => same_entries(a, b, identical),
+    (Value::Record(a), Value::Record(b)) => a.is(&b.ty) && same_items(a.fields.iter(), b.fields.iter(), identical),
+    (Value::Variant(a), Value::Variant(b)) => {
+      a.is(&b.ty) && a.tag == b.tag && same_items(a.fields.iter(), b.fields.iter(), identical)
+    }
+    (a, b) => a == b,
+  }
+}
@@ -0,0 +1,154 @@
+/*
+  Runs programs through both the quick evaluator and the translator + vm, form by form, and reports the
+  first place where the two disagree. This is what keeps the retirement of quick_eval honest.
+*/
+
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::common::prelude::make_std_env_with_output;
+use crate::evaluator::quick_eval::qeval_expr;
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::value::EnvHead;
+use crate::evaluator::vm::Vm;
+use crate::reader::ast::{to_value, Node};
+use crate::reader::reader::Reader;
+use crate::translator::optimizer::OptLevel;
+use crate::translator::translator::Translator;
+
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+  // The printed result, or the error or panic message if evaluation failed
+  pub result: Result<String, String>,
+  pub stdout: String,
+}
+
+impl Outcome {
+  fn agrees_with(&self, other: &Outcome) -> bool {
+    let results_agree = match (&self.result, &other.result) {
+      (Ok(a), Ok(b)) => a == b,
+      // Both evaluators failing is agreement, their messages are allowed to differ
+      // since the vm counts steps per instruction rather than per call
+      (Err(_), Err(_)) => true,
+      _ => false,
+    };
+    results_agree && self.stdout == other.stdout
+  }
+}
+
+impl fmt::Display for Outcome {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.result {
+      Ok(value) => write!(f, "=> {}", value)?,
+      Err(msg) => write!(f, "failed: {}", msg)?,
+    }
+    write!(f, " (stdout: {:?})", self.stdout)
+  }
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+  pub index: usize,
+  pub form: String,
+  pub quick: Outcome,
+  pub vm: Outcome,
+}
+
+impl fmt::Display for Divergence {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Divergence at form #{}: {}", self.index, self.form)?;
+    writeln!(f, "  quick_eval: {}", self.quick)?;
+    write!(f, "  vm:         {}", self.vm)
+  }
+}
+
+#[derive(Debug)]
+pub enum CompareError {
+  // The program could not be read, so nothing was compared
+  Syntax(HarpError),
+  Diverged(Box<Divergence>),
+}
+
+impl fmt::Display for CompareError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CompareError::Syntax(err) => write!(f, "{}", err),
+      CompareError::Diverged(divergence) => write!(f, "{}", divergence),
+    }
+  }
+}
+
+struct Side {
+  env: EnvHead,
+  out: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Side {
+  fn new() -> Side {
+    let out = Rc::new(RefCell::new(Vec::new()));
+    let env = make_std_env_with_output(out.clone());
+    // Both sides draw the same random numbers
+    env.seed_random(0);
+    Side { env, out }
+  }
+
+  fn run<F: FnOnce(&mut EnvHead) -> EvalResult>(&mut self, eval: F) -> Outcome {
+    self.out.borrow_mut().clear();
+    self.env.budget().reset();
+
+    let env = &mut self.env;
+    let result = match catch_unwind(AssertUnwindSafe(|| eval(env))) {
+      Ok(Ok(value)) => Ok(value.to_string()),
+      Ok(Err(err)) => Err(err.to_string()),
+      Err(err) => match (err.downcast_ref::<String>(), err.downcast_ref::<&str>()) {
+        (Some(msg), _) => Err(format!("panicked: {}", msg)),
+        (_, Some(msg)) => Err(format!("panicked: {}", msg)),
+        _ => Err("panicked".to_string()),
+      },
+    };
+
+    Outcome {
+      result,
+      stdout: String::from_utf8_lossy(&self.out.borrow()).to_string(),
+    }
+  }
+}
+
+// Evaluates each top level form with both evaluators, each keeping its own environment
+pub fn compare_progn(progn: &Node, opt_level: OptLevel) -> Result<usize, Box<Divergence>> {
+  let forms = match progn {
+    Node::Progn(ns, _) => ns,
+    otherwise => panic!("Compare expects a progn, but got: {:?}", otherwise),
+  };
+
+  let mut quick = Side::new();
+  let mut vm = Side::new();
+
+  for (index, form) in forms.iter().enumerate() {
+    let quick_outcome = quick.run(|env| qeval_expr(form, env));
+    let vm_outcome = vm.run(|env| {
+      let script = Translator::for_env(opt_level, env).value_to_script(&to_value(form));
+      Vm::with_opt_level(opt_level).eval_script(env, &script)
+    });
+
+    if !quick_outcome.agrees_with(&vm_outcome) {
+      return Err(Box::new(Divergence {
+        index,
+        form: to_value(form).to_string(),
+        quick: quick_outcome,
+        vm: vm_outcome,
+      }));
+    }
+  }
+
+  Ok(forms.len())
+}
+
+pub fn compare_source(code: &str, opt_level: OptLevel) -> Result<usize, CompareError> {
+  match Reader::new(code).next_progn() {
+    Ok(progn) => compare_progn(&progn, opt_level).map_err(CompareError::Diverged),
+    Err(err) => Err(CompareError::Syntax(err)),
+  }
+}
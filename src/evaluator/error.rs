@@ -0,0 +1,47 @@
+use crate::evaluator::value::Value;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HarpError {
+  // The reader could not make sense of the code
+  Syntax(String),
+  // Raised by natives and scripts, can be caught with `try`
+  Runtime(String),
+  // One of the configured execution limits was hit, can be caught with `try`
+  LimitExceeded(String),
+  // Evaluation was stopped by the host, never caught by scripts
+  Interrupted,
+}
+
+pub type EvalResult = Result<Value, HarpError>;
+
+impl HarpError {
+  pub fn runtime<S: Into<String>>(message: S) -> HarpError {
+    HarpError::Runtime(message.into())
+  }
+
+  pub fn message(&self) -> String {
+    match self {
+      HarpError::Syntax(msg) => format!("syntax error: {}", msg),
+      HarpError::Runtime(msg) => msg.clone(),
+      HarpError::LimitExceeded(msg) => format!("limit exceeded: {}", msg),
+      HarpError::Interrupted => "interrupted".to_string(),
+    }
+  }
+}
+
+impl fmt::Display for HarpError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.message())
+  }
+}
+
+impl std::error::Error for HarpError {}
+
+// Shorthand for returning a runtime error from a native, in the style of panic!
+#[macro_export]
+macro_rules! harp_err {
+  ($($arg:tt)*) => {
+    Err($crate::evaluator::error::HarpError::Runtime(format!($($arg)*)))
+  };
+}
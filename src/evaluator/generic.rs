@@ -0,0 +1,189 @@
+/*
+  Generic functions dispatch on the runtime types of their arguments:
+
+    (defgeneric area (shape))
+    (defmethod area ((c Circle)) (* 3 (get c :r) (get c :r)))
+    (defmethod area ((r Rect)) (* (get r :w) (get r :h)))
+    (defmethod area (shape) 0)
+
+  A parameter written as (name Type) only accepts values of that type, plain parameters accept anything, so the
+  last method is the default. Types are the names type-of returns: Number, String, Keyword, Atom, Bool, Unit,
+  List, Vector, Map, Lazy, Regex, Function or the name of a record, deftype or host type, variants also match their own name.
+  Of the methods taking the arguments, the one with the most typed parameters wins, a variant name counting more
+  than the name of its type, and later methods win ties.
+
+  defmethod on a function which is not generic yet turns it into one, keeping the function as the fallback when
+  no method applies. The prelude's +, -, * and eq (also bound as equal?) are generic this way, as is show which
+  print uses to turn values into text.
+*/
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::evaluator::error::HarpError;
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::quick_eval::qapply;
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+use crate::harp_err;
+
+struct Method {
+  types: Vec<Option<String>>,
+  arity: Arity,
+  func: Value,
+}
+
+pub struct Generic {
+  name: String,
+  arity: Arity,
+  methods: Vec<Method>,
+  fallback: Option<Value>,
+}
+
+// Generic functions by name with the native bound for them, shared by every scope of an environment
+pub type Generics = Rc<RefCell<HashMap<String, (Native, Rc<RefCell<Generic>>)>>>;
+
+pub fn type_of(value: &Value) -> String {
+  let name = match value {
+    Value::Unit => "Unit",
+    Value::Number(_) => "Number",
+    Value::String(_) => "String",
+    Value::Atom(name) if is_keyword(name) => "Keyword",
+    Value::Atom(_) => "Atom",
+    Value::Bool(_) => "Bool",
+    Value::List(_) => "List",
+    Value::Vector(_) => "Vector",
+    Value::Map(_) => "Map",
+    Value::Lazy(_) => "Lazy",
+    Value::Regex(_) => "Regex",
+    Value::Do(_) => "Do",
+    Value::NativeFunc(_) | Value::Func(_, _, _) => "Function",
+    Value::UserData(data) => data.type_name(),
+    Value::Record(record) => &record.ty.name,
+    Value::Variant(variant) => &variant.ty.name,
+  };
+  name.to_string()
+}
+
+// How closely a parameter type fits a value, a variant fits its own name better than the name of its type
+fn fit(value: &Value, ty: &Option<String>) -> Option<usize> {
+  match (value, ty) {
+    (_, None) => Some(0),
+    (Value::Variant(variant), Some(ty)) if variant.name() == ty => Some(2),
+    (value, Some(ty)) if type_of(value) == *ty => Some(1),
+    _ => None,
+  }
+}
+
+impl Generic {
+  // The most specific method taking the arguments, or the fallback
+  fn select(&self, args: &[Value]) -> Option<Value> {
+    let mut best: Option<(usize, &Method)> = None;
+    for method in &self.methods {
+      if !method.arity.accepts(args.len()) {
+        continue;
+      }
+      let fits: Option<Vec<usize>> = method.types.iter().zip(args).map(|(ty, arg)| fit(arg, ty)).collect();
+      if let Some(score) = fits.map(|fits| fits.iter().sum::<usize>()) {
+        if best.is_none_or(|(most, _)| score >= most) {
+          best = Some((score, method));
+        }
+      }
+    }
+    match best {
+      Some((_, method)) => Some(method.func.clone()),
+      None => self.fallback.clone(),
+    }
+  }
+}
+
+fn dispatcher(generic: Rc<RefCell<Generic>>) -> Native {
+  let name = generic.borrow().name.clone();
+  Native::new(&name, Arity::any(), move |args, env| {
+    let selected = {
+      let generic = generic.borrow();
+      generic.arity.check(&generic.name, args.len())?;
+      generic.select(&args)
+    };
+    match selected {
+      Some(func) => qapply(func, args, env),
+      None => {
+        let types: Vec<String> = args.iter().map(type_of).collect();
+        harp_err!("{} has no method for ({})", generic.borrow().name, types.join(" "))
+      }
+    }
+  })
+}
+
+// The generic function bound to name, making one if the name is unbound or bound to a regular function
+pub fn generic_for(name: &str, arity: Arity, env: &mut EnvHead) -> Result<Rc<RefCell<Generic>>, HarpError> {
+  let current = env.get(name.to_string());
+  let generics = env.generics();
+  if let (Some(Value::NativeFunc(bound)), Some((native, generic))) = (&current, generics.borrow().get(name)) {
+    if bound.ptr_eq(native) {
+      return Ok(generic.clone());
+    }
+  }
+
+  let fallback = match current {
+    Some(Value::NativeFunc(native)) if native.special => {
+      return harp_err!("{} is a special form and can't have methods", name)
+    }
+    Some(func @ Value::NativeFunc(_)) | Some(func @ Value::Func(_, _, _)) => Some(func),
+    _ => None,
+  };
+  let generic = Rc::new(RefCell::new(Generic {
+    name: name.to_string(),
+    arity,
+    methods: Vec::new(),
+    fallback,
+  }));
+  let native = dispatcher(generic.clone());
+  generics.borrow_mut().insert(name.to_string(), (native.clone(), generic.clone()));
+  env.set(name.to_string(), Value::NativeFunc(native));
+  Ok(generic)
+}
+
+// Binds name to the generic function bound to existing, methods added through either name apply to both
+pub fn alias(name: &str, existing: &str, env: &mut EnvHead) -> Result<(), HarpError> {
+  let generic = generic_for(existing, Arity::any(), env)?;
+  let generics = env.generics();
+  let native = match generics.borrow().get(existing) {
+    Some((native, _)) => native.clone(),
+    None => return harp_err!("{} is not a generic function", existing),
+  };
+  generics.borrow_mut().insert(name.to_string(), (native.clone(), generic));
+  env.set(name.to_string(), Value::NativeFunc(native));
+  Ok(())
+}
+
+// The generic functions which have been given methods, calls to them can't be folded as the prelude's
+pub fn with_methods(generics: &Generics) -> HashSet<String> {
+  let generics = generics.borrow();
+  let named = generics.iter().filter(|(_, (_, generic))| !generic.borrow().methods.is_empty());
+  named.map(|(name, _)| name.clone()).collect()
+}
+
+// Types has an entry per required parameter of func, None for parameters of any type
+pub fn add_method(name: &str, types: Vec<Option<String>>, func: Value, env: &mut EnvHead) -> Result<(), HarpError> {
+  let arity = match &func {
+    Value::Func(_, params, _) => params.arity(),
+    Value::NativeFunc(native) => native.arity,
+    v => return harp_err!("Defmethod expected a function, but got {}", v),
+  };
+  let generic = generic_for(name, Arity::any(), env)?;
+  generic.borrow_mut().methods.push(Method { types, arity, func });
+  Ok(())
+}
+
+// The text print writes for a value, through the show generic function
+pub fn show(value: Value, env: &mut EnvHead) -> Result<String, HarpError> {
+  let show = match env.get("show".to_string()) {
+    Some(show @ Value::NativeFunc(_)) | Some(show @ Value::Func(_, _, _)) => show,
+    _ => return Ok(value.to_string()),
+  };
+  match qapply(show, vec![value], env)? {
+    Value::String(text) => Ok(text),
+    v => Ok(v.to_string()),
+  }
+}
@@ -0,0 +1,103 @@
+/*
+  The one stop embedding api: an environment with the standard library, output sinks and limits, and
+  methods to evaluate code and exchange values with it. Every evaluation starts with a fresh budget.
+*/
+
+use std::fs;
+use std::path::Path;
+
+use crate::common::prelude::make_std_env;
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::limits::Limits;
+use crate::evaluator::native::IntoNative;
+use crate::evaluator::quick_eval::{qapply, qeval_progn};
+use crate::evaluator::value::{EnvHead, Output, Value};
+use crate::reader::reader::Reader;
+
+pub struct Interpreter {
+  env: EnvHead,
+}
+
+impl Default for Interpreter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Interpreter {
+  pub fn new() -> Interpreter {
+    Interpreter {
+      env: make_std_env(),
+    }
+  }
+
+  pub fn with_outputs(stdout: Output, stderr: Output) -> Interpreter {
+    let mut interp = Interpreter::new();
+    interp.set_stdout(stdout);
+    interp.set_stderr(stderr);
+    interp
+  }
+
+  pub fn set_stdout(&mut self, out: Output) {
+    self.env.set_output(out);
+  }
+
+  pub fn set_stderr(&mut self, err: Output) {
+    self.env.set_error_output(err);
+  }
+
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.env.budget().set_limits(limits);
+  }
+
+  // For hosts that need reproducible runs, see common/random.rs
+  pub fn seed_random(&mut self, seed: u64) {
+    self.env.seed_random(seed);
+  }
+
+  pub fn env(&mut self) -> &mut EnvHead {
+    &mut self.env
+  }
+
+  pub fn eval_str(&mut self, code: &str) -> EvalResult {
+    let progn = Reader::new(code).next_progn()?;
+    self.env.budget().reset();
+    qeval_progn(&progn, &mut self.env)
+  }
+
+  pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> EvalResult {
+    match fs::read_to_string(path.as_ref()) {
+      Ok(code) => self.eval_str(&code),
+      Err(err) => Err(HarpError::Runtime(format!(
+        "Failed to read {}: {}",
+        path.as_ref().display(),
+        err
+      ))),
+    }
+  }
+
+  // Calls a global function with arguments that are passed as is, without being evaluated
+  pub fn call(&mut self, name: &str, args: Vec<Value>) -> EvalResult {
+    match self.env.get(name.to_string()) {
+      Some(callee @ Value::Func(_, _, _)) | Some(callee @ Value::NativeFunc(_)) => {
+        self.env.budget().reset();
+        qapply(callee, args, &mut self.env)
+      }
+      Some(v) => Err(HarpError::Runtime(format!("{} is not a function, it is {}", name, v))),
+      None => Err(HarpError::Runtime(format!("Undefined function {}", name))),
+    }
+  }
+
+  // Registers a Rust closure, e.g. interp.register("add", |a: f64, b: f64| a + b)
+  pub fn register<Args, F: IntoNative<Args>>(&mut self, name: &str, func: F) {
+    self.env.register(name, func);
+  }
+
+  pub fn get_global(&self, name: &str) -> Option<Value> {
+    self.env.get(name.to_string())
+  }
+
+  pub fn set_global(&mut self, name: &str, value: Value) {
+    self.env.set(name.to_string(), value);
+  }
+}
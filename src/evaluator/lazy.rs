@@ -0,0 +1,314 @@
+/*
+  Lazy sequences, computed an element at a time as they're walked and remembered once computed:
+
+    (defun naturals (n) (lazy-seq (cons n (naturals (+ n 1)))))
+    (take 3 (naturals 0))
+
+  lazy-seq evaluates its body the first time the sequence is walked, in the scope it was made in, and the body
+  returns the sequence's contents: a list, vector or string, (), or another lazy sequence. cons onto a lazy
+  sequence makes one without walking it. iterate, repeat and cycle make infinite ones, and first, rest, nth,
+  take, drop, map, filter and zip walk only as far as they need to and return lazy sequences for lazy input.
+  Everything else in the sequence library realizes the whole sequence first, which never ends for an infinite
+  one unless the limits stop it.
+
+  Lazy sequences print as far as they're realized, Lazy(0 1 ...), print and doall realize them. Host code can
+  stream values into Harp with LazySeq::stream and walk a sequence with LazySeq::iter.
+*/
+
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use crate::evaluator::collections::elements;
+use crate::evaluator::error::HarpError;
+use crate::evaluator::quick_eval::{qapply, qeval_value};
+use crate::evaluator::value::{EnvHead, Value};
+use crate::harp_err;
+
+// The first element and the rest, or None at the end
+pub type Step = Option<(Value, LazySeq)>;
+
+// Called again after a limit or an interrupt stops it, so the sequence can be walked once the budget is reset
+type Thunk = Box<dyn FnMut(&mut EnvHead) -> Result<Step, HarpError>>;
+
+enum State {
+  Pending(Thunk),
+  Realizing,
+  Done(Step),
+  Failed(HarpError),
+}
+
+#[derive(Clone)]
+pub struct LazySeq(Rc<RefCell<State>>);
+
+impl LazySeq {
+  pub fn new(thunk: impl FnMut(&mut EnvHead) -> Result<Step, HarpError> + 'static) -> LazySeq {
+    LazySeq(Rc::new(RefCell::new(State::Pending(Box::new(thunk)))))
+  }
+
+  pub fn empty() -> LazySeq {
+    LazySeq(Rc::new(RefCell::new(State::Done(None))))
+  }
+
+  pub fn cons(value: Value, rest: LazySeq) -> LazySeq {
+    LazySeq(Rc::new(RefCell::new(State::Done(Some((value, rest))))))
+  }
+
+  // Values from a host iterator, pulled as Harp walks the sequence
+  pub fn stream(iter: impl Iterator<Item = Value> + 'static) -> LazySeq {
+    fn next(iter: Rc<RefCell<dyn Iterator<Item = Value>>>) -> LazySeq {
+      LazySeq::new(move |_| {
+        let value = iter.borrow_mut().next();
+        Ok(value.map(|value| (value, next(iter.clone()))))
+      })
+    }
+    next(Rc::new(RefCell::new(iter)))
+  }
+
+  // A lazy sequence over the elements of any other sequence
+  pub fn of(value: &Value) -> Result<LazySeq, HarpError> {
+    match value {
+      Value::Lazy(seq) => Ok(seq.clone()),
+      value => match elements(value) {
+        Some(items) => Ok(LazySeq::stream(items.into_iter())),
+        None => harp_err!("Expected a sequence, but got {}", value),
+      },
+    }
+  }
+
+  pub fn ptr_eq(&self, other: &LazySeq) -> bool {
+    Rc::ptr_eq(&self.0, &other.0)
+  }
+
+  pub fn id(&self) -> usize {
+    Rc::as_ptr(&self.0) as *const () as usize
+  }
+
+  // Computes the first element if it isn't yet
+  pub fn force(&self, env: &mut EnvHead) -> Result<Step, HarpError> {
+    let state = mem::replace(&mut *self.0.borrow_mut(), State::Realizing);
+    let (state, res) = match state {
+      State::Pending(mut thunk) => match thunk(env) {
+        Ok(step) => (State::Done(step.clone()), Ok(step)),
+        // Only errors of the sequence itself are remembered, not running out of budget or being stopped
+        Err(err @ HarpError::Runtime(_)) | Err(err @ HarpError::Syntax(_)) => (State::Failed(err.clone()), Err(err)),
+        Err(err) => (State::Pending(thunk), Err(err)),
+      },
+      State::Done(step) => (State::Done(step.clone()), Ok(step)),
+      State::Failed(err) => (State::Failed(err.clone()), Err(err)),
+      State::Realizing => return harp_err!("A lazy sequence needs its own elements to compute them"),
+    };
+    *self.0.borrow_mut() = state;
+    res
+  }
+
+  // The sequence after its first element, computed when walked
+  pub fn rest(&self) -> LazySeq {
+    let seq = self.clone();
+    LazySeq::new(move |env| match seq.force(env)? {
+      Some((_, rest)) => rest.force(env),
+      None => Ok(None),
+    })
+  }
+
+  pub fn iter<'a>(&self, env: &'a mut EnvHead) -> Iter<'a> {
+    Iter {
+      next: Some(self.clone()),
+      env,
+    }
+  }
+
+  // The element at index, an error realizing it or one before it is returned as it is
+  pub fn nth(&self, index: usize, env: &mut EnvHead) -> Result<Option<Value>, HarpError> {
+    for (i, item) in self.iter(env).enumerate() {
+      let item = item?;
+      if i == index {
+        return Ok(Some(item));
+      }
+    }
+    Ok(None)
+  }
+
+  // Realizes the whole sequence
+  pub fn to_vec(&self, env: &mut EnvHead) -> Result<Vec<Value>, HarpError> {
+    self.iter(env).collect()
+  }
+
+  // The realized element after which the sequence continues, without computing anything
+  fn realized(&self) -> Option<Step> {
+    match &*self.0.borrow() {
+      State::Done(step) => Some(step.clone()),
+      _ => None,
+    }
+  }
+}
+
+// Long realized sequences are dropped cell by cell instead of recursively
+impl Drop for LazySeq {
+  fn drop(&mut self) {
+    let mut next = take_rest(self);
+    while let Some(mut seq) = next {
+      next = take_rest(&mut seq);
+    }
+  }
+}
+
+fn take_rest(seq: &mut LazySeq) -> Option<LazySeq> {
+  if Rc::strong_count(&seq.0) != 1 {
+    return None;
+  }
+  match mem::replace(&mut *seq.0.borrow_mut(), State::Done(None)) {
+    State::Done(Some((_, rest))) => Some(rest),
+    _ => None,
+  }
+}
+
+// Walks a sequence from Rust, every element counts as a step against the limits
+pub struct Iter<'a> {
+  next: Option<LazySeq>,
+  env: &'a mut EnvHead,
+}
+
+impl Iterator for Iter<'_> {
+  type Item = Result<Value, HarpError>;
+
+  fn next(&mut self) -> Option<Result<Value, HarpError>> {
+    let seq = self.next.take()?;
+    if let Err(err) = self.env.budget().step() {
+      return Some(Err(err));
+    }
+    match seq.force(self.env) {
+      Ok(Some((value, rest))) => {
+        self.next = Some(rest);
+        Some(Ok(value))
+      }
+      Ok(None) => None,
+      Err(err) => Some(Err(err)),
+    }
+  }
+}
+
+impl fmt::Display for LazySeq {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Lazy(")?;
+    let mut seq = self.clone();
+    let mut first = true;
+    loop {
+      let (value, rest) = match seq.realized() {
+        Some(Some(step)) => step,
+        Some(None) => break,
+        None => {
+          write!(f, "{}...", if first { "" } else { " " })?;
+          break;
+        }
+      };
+      write!(f, "{}{}", if first { "" } else { " " }, value)?;
+      first = false;
+      seq = rest;
+    }
+    write!(f, ")")
+  }
+}
+
+// The step of a sequence a lazy-seq body returned
+fn step_of(value: Value, env: &mut EnvHead) -> Result<Step, HarpError> {
+  match value {
+    Value::Lazy(seq) => seq.force(env),
+    value => match elements(&value) {
+      Some(_) => LazySeq::of(&value)?.force(env),
+      None => harp_err!("Lazy-seq expected its body to return a sequence, but got {}", value),
+    },
+  }
+}
+
+// (lazy-seq body...), the body runs in a copy of the scope it was made in, see EnvHead::capture
+pub fn lazy_body(body: Value, env: &EnvHead) -> LazySeq {
+  let mut scope = env.capture();
+  LazySeq::new(move |_| {
+    let value = qeval_value(body.clone(), &mut scope)?;
+    step_of(value, &mut scope)
+  })
+}
+
+// x, (f x), (f (f x)) ...
+pub fn iterate(f: Value, x: Value) -> LazySeq {
+  let seed = x.clone();
+  LazySeq::cons(
+    x,
+    LazySeq::new(move |env| {
+      let next = qapply(f.clone(), vec![seed.clone()], env)?;
+      iterate(f.clone(), next).force(env)
+    }),
+  )
+}
+
+pub fn repeat(x: Value) -> LazySeq {
+  LazySeq::new(move |_| Ok(Some((x.clone(), repeat(x.clone())))))
+}
+
+// The items over and over, nothing when there are none
+pub fn cycle(items: Rc<Vec<Value>>, i: usize) -> LazySeq {
+  LazySeq::new(move |_| match items.get(i) {
+    Some(x) => Ok(Some((x.clone(), cycle(items.clone(), (i + 1) % items.len())))),
+    None => Ok(None),
+  })
+}
+
+pub fn take(n: usize, seq: LazySeq) -> LazySeq {
+  if n == 0 {
+    return LazySeq::empty();
+  }
+  LazySeq::new(move |env| Ok(seq.force(env)?.map(|(x, rest)| (x, take(n - 1, rest)))))
+}
+
+pub fn drop(n: usize, seq: LazySeq) -> LazySeq {
+  LazySeq::new(move |env| {
+    let mut seq = seq.clone();
+    for _ in 0..n {
+      env.budget().step()?;
+      match seq.force(env)? {
+        Some((_, rest)) => seq = rest,
+        None => return Ok(None),
+      }
+    }
+    seq.force(env)
+  })
+}
+
+// f applied to an element of each sequence, ending with the shortest
+pub fn map(f: Value, seqs: Vec<LazySeq>) -> LazySeq {
+  LazySeq::new(move |env| {
+    let mut args = Vec::with_capacity(seqs.len());
+    let mut rests = Vec::with_capacity(seqs.len());
+    for seq in &seqs {
+      match seq.force(env)? {
+        Some((x, rest)) => {
+          args.push(x);
+          rests.push(rest);
+        }
+        None => return Ok(None),
+      }
+    }
+    let value = qapply(f.clone(), args, env)?;
+    Ok(Some((value, map(f.clone(), rests))))
+  })
+}
+
+// The elements pred returns #t for
+pub fn filter(pred: Value, seq: LazySeq) -> LazySeq {
+  LazySeq::new(move |env| {
+    let mut seq = seq.clone();
+    loop {
+      env.budget().step()?;
+      match seq.force(env)? {
+        Some((x, rest)) => match qapply(pred.clone(), vec![x.clone()], env)? {
+          Value::Bool(true) => return Ok(Some((x, filter(pred.clone(), rest)))),
+          Value::Bool(false) => seq = rest,
+          v => return harp_err!("Filter expected its predicate to return a boolean, but got {}", v),
+        },
+        None => return Ok(None),
+      }
+    }
+  })
+}
@@ -0,0 +1,138 @@
+/*
+  Execution limits shared by every scope of an environment. Both evaluators report a step per call (quick_eval)
+  or instruction (vm), enter/leave around function calls, and count values as they are bound or produced by
+  natives. The interrupt flag is checked on every step so the host can stop a runaway evaluation.
+*/
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::evaluator::error::HarpError;
+use crate::evaluator::value::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+  pub max_steps: Option<u64>,
+  pub max_depth: Option<usize>,
+  pub max_values: Option<usize>,
+}
+
+// Deep enough for real scripts, shallow enough to fail before the Rust stack does
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+impl Default for Limits {
+  fn default() -> Self {
+    Limits {
+      max_steps: None,
+      max_depth: Some(DEFAULT_MAX_DEPTH),
+      max_values: None,
+    }
+  }
+}
+
+impl Limits {
+  pub fn unlimited() -> Limits {
+    Limits {
+      max_steps: None,
+      max_depth: None,
+      max_values: None,
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct Budget {
+  limits: Cell<Limits>,
+  steps: Cell<u64>,
+  depth: Cell<usize>,
+  values: Cell<usize>,
+  interrupt: Arc<AtomicBool>,
+}
+
+// Number of values a value accounts for. Collections count their elements but not nested ones, which were
+// counted when they were made, so weighing a shared collection doesn't walk it
+pub fn weight(value: &Value) -> usize {
+  match value {
+    Value::List(xs) => 1 + xs.len(),
+    Value::Vector(xs) => 1 + xs.len(),
+    Value::Do(xs) => 1 + xs.len(),
+    Value::Record(record) => 1 + record.fields.len(),
+    Value::Variant(variant) => 1 + variant.fields.len(),
+    Value::Map(entries) => 1 + 2 * entries.len(),
+    _ => 1,
+  }
+}
+
+impl Budget {
+  pub fn limits(&self) -> Limits {
+    self.limits.get()
+  }
+
+  pub fn set_limits(&self, limits: Limits) {
+    self.limits.set(limits);
+  }
+
+  // Flag which, once set from any thread, interrupts the evaluation on its next step
+  pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+    self.interrupt.clone()
+  }
+
+  // Starts a fresh evaluation, the limits themselves are kept
+  pub fn reset(&self) {
+    self.steps.set(0);
+    self.depth.set(0);
+    self.values.set(0);
+    self.interrupt.store(false, Ordering::SeqCst);
+  }
+
+  pub fn steps(&self) -> u64 {
+    self.steps.get()
+  }
+
+  pub fn step(&self) -> Result<(), HarpError> {
+    if self.interrupt.load(Ordering::Relaxed) {
+      return Err(HarpError::Interrupted);
+    }
+
+    let steps = self.steps.get() + 1;
+    self.steps.set(steps);
+    match self.limits.get().max_steps {
+      Some(max) if steps > max => Err(HarpError::LimitExceeded(format!(
+        "evaluation took more than {} steps",
+        max
+      ))),
+      _ => Ok(()),
+    }
+  }
+
+  pub fn enter(&self) -> Result<(), HarpError> {
+    let depth = self.depth.get() + 1;
+    match self.limits.get().max_depth {
+      Some(max) if depth > max => Err(HarpError::LimitExceeded(format!(
+        "call depth exceeded {}",
+        max
+      ))),
+      _ => {
+        self.depth.set(depth);
+        Ok(())
+      }
+    }
+  }
+
+  pub fn leave(&self) {
+    self.depth.set(self.depth.get().saturating_sub(1));
+  }
+
+  pub fn alloc(&self, value: &Value) -> Result<(), HarpError> {
+    let values = self.values.get() + weight(value);
+    self.values.set(values);
+    match self.limits.get().max_values {
+      Some(max) if values > max => Err(HarpError::LimitExceeded(format!(
+        "more than {} values allocated",
+        max
+      ))),
+      _ => Ok(()),
+    }
+  }
+}
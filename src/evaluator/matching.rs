@@ -0,0 +1,229 @@
+/*
+  Patterns of the match form. Clauses are a pattern, an optional `:when guard` and a body:
+
+    (match command
+      (:go dir) (walk dir)
+      (:take item) :when (here? item) (take item)
+      [x y] (+ x y)
+      {:type :door :open open} open
+      'look (describe room)
+      (Some item) (take item)
+      _ (println "What?"))
+
+  Literals and keywords match equal values, 'x matches the atom x, _ matches anything and other names
+  match anything and bind it. Lists, vectors and maps match their elements, with `& rest` for the
+  remaining elements of a sequence, and maps (or records) need every key of the pattern. Names starting
+  with an uppercase letter are variants of a deftype, (Some x) matches the variant Some and its fields and
+  None matches a variant without fields.
+*/
+
+use crate::evaluator::adt::is_variant_name;
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::quick_eval::qeval_value;
+use crate::evaluator::value::{is_keyword, lookup, EnvHead, Value};
+use crate::harp_err;
+
+#[derive(Clone)]
+pub enum MatchPattern {
+  Wildcard,
+  Literal(Value),
+  Bind(String),
+  List(Vec<MatchPattern>, Option<Box<MatchPattern>>),
+  Vector(Vec<MatchPattern>, Option<Box<MatchPattern>>),
+  Map(Vec<(Value, MatchPattern)>),
+  Variant(String, Vec<MatchPattern>),
+}
+
+#[derive(Clone)]
+pub struct Clause {
+  pub pattern: MatchPattern,
+  pub guard: Option<Value>,
+  pub body: Value,
+}
+
+pub fn no_match(value: &Value) -> HarpError {
+  HarpError::Runtime(format!("No match clause matched {}", value))
+}
+
+fn parse_seq(xs: &[Value]) -> Result<(Vec<MatchPattern>, Option<Box<MatchPattern>>), HarpError> {
+  let mut items = Vec::new();
+  let mut xs = xs.iter();
+  while let Some(x) = xs.next() {
+    match x {
+      Value::Atom(marker) if marker == "&" => {
+        let rest = match xs.next() {
+          Some(rest) => MatchPattern::parse(rest)?,
+          None => return harp_err!("Match expected a pattern after &"),
+        };
+        if let Some(extra) = xs.next() {
+          return harp_err!("Match got {} after the rest pattern", extra);
+        }
+        return Ok((items, Some(Box::new(rest))));
+      }
+      x => items.push(MatchPattern::parse(x)?),
+    }
+  }
+  Ok((items, None))
+}
+
+impl MatchPattern {
+  pub fn parse(value: &Value) -> Result<MatchPattern, HarpError> {
+    match value {
+      Value::Atom(name) if name == "_" => Ok(MatchPattern::Wildcard),
+      Value::Atom(name) if is_keyword(name) => Ok(MatchPattern::Literal(value.clone())),
+      Value::Atom(name) if is_variant_name(name) => Ok(MatchPattern::Variant(name.clone(), Vec::new())),
+      Value::Atom(name) => Ok(MatchPattern::Bind(name.clone())),
+      Value::Number(_) | Value::String(_) | Value::Bool(_) => Ok(MatchPattern::Literal(value.clone())),
+      Value::List(xs) => match &xs.to_vec()[..] {
+        [Value::Atom(quote), datum] if quote == "quote" => Ok(MatchPattern::Literal(datum.clone())),
+        [Value::Atom(name), fields @ ..] if is_variant_name(name) => {
+          let fields = fields.iter().map(MatchPattern::parse).collect::<Result<_, _>>()?;
+          Ok(MatchPattern::Variant(name.clone(), fields))
+        }
+        xs => {
+          let (items, rest) = parse_seq(xs)?;
+          Ok(MatchPattern::List(items, rest))
+        }
+      },
+      Value::Vector(xs) => {
+        let (items, rest) = parse_seq(&xs.iter().cloned().collect::<Vec<_>>())?;
+        Ok(MatchPattern::Vector(items, rest))
+      }
+      Value::Map(entries) => {
+        let mut patterns = Vec::new();
+        for (key, pattern) in entries {
+          patterns.push((key.clone(), MatchPattern::parse(pattern)?));
+        }
+        Ok(MatchPattern::Map(patterns))
+      }
+      v => harp_err!("Match can't use {} as a pattern", v),
+    }
+  }
+
+  // Collects the bindings of a successful match
+  pub fn matches(&self, value: &Value, bindings: &mut Vec<(String, Value)>) -> bool {
+    match (self, value) {
+      (MatchPattern::Wildcard, _) => true,
+      (MatchPattern::Literal(literal), value) => literal == value,
+      (MatchPattern::Bind(name), value) => {
+        bindings.push((name.clone(), value.clone()));
+        true
+      }
+      (MatchPattern::List(items, rest), Value::List(xs)) => {
+        match_seq(items, rest, xs.iter(), || Value::List(xs.skip(items.len())), bindings)
+      }
+      (MatchPattern::List(items, rest), Value::Unit) => {
+        match_seq(items, rest, [].iter(), || Value::list(Vec::new()), bindings)
+      }
+      (MatchPattern::Vector(items, rest), Value::Vector(xs)) => {
+        let tail = || Value::list(xs.iter().skip(items.len()).cloned().collect());
+        match_seq(items, rest, xs.iter(), tail, bindings)
+      }
+      (MatchPattern::Variant(name, fields), Value::Variant(variant)) => {
+        variant.name() == name
+          && variant.fields.len() == fields.len()
+          && fields.iter().zip(&variant.fields).all(|(field, x)| field.matches(x, bindings))
+      }
+      (MatchPattern::Map(patterns), Value::Map(_))
+      | (MatchPattern::Map(patterns), Value::Record(_))
+      | (MatchPattern::Map(patterns), Value::Variant(_)) => {
+        patterns.iter().all(|(key, pattern)| match lookup(value, key) {
+          Some(value) => pattern.matches(value, bindings),
+          None => false,
+        })
+      }
+      _ => false,
+    }
+  }
+
+  // Adds the names the pattern binds when it matches
+  pub fn bound_names(&self, names: &mut Vec<String>) {
+    match self {
+      MatchPattern::Bind(name) => names.push(name.clone()),
+      MatchPattern::List(items, rest) | MatchPattern::Vector(items, rest) => {
+        items.iter().chain(rest.as_deref()).for_each(|item| item.bound_names(names))
+      }
+      MatchPattern::Map(entries) => entries.iter().for_each(|(_, pattern)| pattern.bound_names(names)),
+      MatchPattern::Variant(_, fields) => fields.iter().for_each(|field| field.bound_names(names)),
+      MatchPattern::Wildcard | MatchPattern::Literal(_) => {}
+    }
+  }
+
+  // Literal patterns are the ones a jump table can dispatch on
+  pub fn literal(&self) -> Option<&Value> {
+    match self {
+      MatchPattern::Literal(value) => Some(value),
+      _ => None,
+    }
+  }
+}
+
+// The elements are matched where they are, tail makes the list of the ones after items for a rest pattern
+fn match_seq<'a>(
+  items: &[MatchPattern],
+  rest: &Option<Box<MatchPattern>>,
+  xs: impl ExactSizeIterator<Item = &'a Value>,
+  tail: impl FnOnce() -> Value,
+  bindings: &mut Vec<(String, Value)>,
+) -> bool {
+  let fits = match rest {
+    Some(_) => xs.len() >= items.len(),
+    None => xs.len() == items.len(),
+  };
+  if !fits || !items.iter().zip(xs).all(|(item, x)| item.matches(x, bindings)) {
+    return false;
+  }
+  match rest {
+    Some(rest) => rest.matches(&tail(), bindings),
+    None => true,
+  }
+}
+
+pub fn parse_clauses(xs: &[Value]) -> Result<Vec<Clause>, HarpError> {
+  let mut clauses = Vec::new();
+  let mut xs = xs.iter();
+  while let Some(syntax) = xs.next() {
+    let pattern = MatchPattern::parse(syntax)?;
+    let when = Value::Atom(":when".to_string());
+    let mut next = xs.next();
+    let mut guard = None;
+    if next == Some(&when) {
+      guard = xs.next().cloned();
+      next = xs.next();
+    }
+    match next {
+      Some(body) if body != &when => clauses.push(Clause {
+        pattern,
+        guard,
+        body: body.clone(),
+      }),
+      _ => return harp_err!("Match expected a body after the pattern {}", syntax),
+    }
+  }
+  Ok(clauses)
+}
+
+// Runs the body of the first clause whose pattern matches and whose guard holds
+pub fn eval_match(value: Value, clauses: &[Clause], env: &mut EnvHead) -> EvalResult {
+  for clause in clauses {
+    let mut bindings = Vec::new();
+    if !clause.pattern.matches(&value, &mut bindings) {
+      continue;
+    }
+
+    let mut scope = env.clone().push();
+    for (name, value) in bindings {
+      scope.budget().alloc(&value)?;
+      scope.set(name, value);
+    }
+    if let Some(guard) = &clause.guard {
+      match qeval_value(guard.clone(), &mut scope)? {
+        Value::Bool(true) => {}
+        Value::Bool(false) => continue,
+        v => return harp_err!("Match expected its guard to evaluate to boolean, but got {}", v),
+      }
+    }
+    return qeval_value(clause.body.clone(), &mut scope);
+  }
+  Err(no_match(&value))
+}
@@ -1,9 +1,32 @@
+pub mod adt;
+pub mod collections;
+pub mod compare;
+pub mod differential;
+pub mod error;
+pub mod generic;
+pub mod interpreter;
+pub mod lazy;
+pub mod limits;
+pub mod matching;
+pub mod native;
 pub mod opcodes;
+pub mod params;
+pub mod pattern;
 pub mod quick_eval;
+pub mod re;
+pub mod record;
 pub mod script;
+pub mod userdata;
 pub mod value;
 pub mod vm;
 
+#[cfg(test)]
+use crate::translator::optimizer::OptLevel;
+#[cfg(test)]
+use differential::compare_source;
+#[cfg(test)]
+use std::{fs, path::Path};
+#[cfg(test)]
 use value::*;
 
 #[test]
@@ -18,10 +41,820 @@ fn value_env_test() {
   assert_eq!(env.get("global".to_string()), Some(Value::Number(123.0)));
   assert_eq!(env.get("local".to_string()), Some(Value::Bool(true)));
 
-  match env.pop() {
-    Some(env) => {
-      assert_eq!(env.get("local".to_string()), None);
+  if let Some(env) = env.pop() {
+    assert_eq!(env.get("local".to_string()), None);
+  }
+}
+
+#[test]
+fn differential_inline_test() {
+  let programs = [
+    "(+ 1 2 3)",
+    "(def x 10) (set! x (* x 2)) (- x 5)",
+    "(if (eq 1 1) \"yes\" \"no\") (if (not #t) 1)",
+    "(defun sq (n) (* n n)) (sq 12)",
+    "((lambda (a b) (+ a b)) 3 4)",
+    "(print 1 \"two\" #t) (println '(quoted list))",
+  ];
+
+  for program in programs.iter() {
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2].iter() {
+      if let Err(divergence) = compare_source(program, *level) {
+        panic!("{} at {:?}\n{}", program, level, divergence);
+      }
+    }
+  }
+}
+
+#[test]
+fn differential_shared_errors_test() {
+  // Redefining is an error in both evaluators, which counts as agreement
+  match compare_source("(def a 1) (def a 2)", OptLevel::O0) {
+    Ok(forms) => assert_eq!(forms, 2),
+    Err(divergence) => panic!("{}", divergence),
+  }
+}
+
+#[test]
+fn differential_corpus_test() {
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let mut failures = Vec::new();
+
+  for dir in ["examples", "tests/corpus"].iter() {
+    let mut paths: Vec<_> = fs::read_dir(root.join(dir))
+      .unwrap()
+      .map(|entry| entry.unwrap().path())
+      .filter(|path| path.extension().is_some_and(|ext| ext == "harp"))
+      .collect();
+    paths.sort();
+
+    for path in paths {
+      let code = fs::read_to_string(&path).unwrap();
+      for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2].iter() {
+        if let Err(divergence) = compare_source(&code, *level) {
+          failures.push(format!("{} at {:?}: {}", path.display(), level, divergence));
+        }
+      }
+    }
+  }
+
+  assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[cfg(test)]
+fn eval_limited(code: &str, limits: limits::Limits) -> error::EvalResult {
+  let mut env = crate::common::prelude::make_std_env();
+  env.budget().set_limits(limits);
+  quick_eval::qeval_progn(&crate::reader::reader::Reader::new(code).next_progn()?, &mut env)
+}
+
+#[test]
+fn limits_test() {
+  let spin = "(defun spin (n) (if (eq n 0) 0 (spin (- n 1)))) ";
+  let limits = limits::Limits {
+    max_steps: Some(100),
+    max_depth: Some(50),
+    max_values: None,
+  };
+
+  assert_eq!(eval_limited(&format!("{}(spin 5)", spin), limits), Ok(Value::Number(0.0)));
+
+  match eval_limited(&format!("{}(spin 40)", spin), limits) {
+    Err(error::HarpError::LimitExceeded(msg)) => assert!(msg.contains("100 steps")),
+    res => panic!("Expected the step limit to be hit, got {:?}", res),
+  }
+
+  let limits = limits::Limits {
+    max_steps: None,
+    ..limits
+  };
+  match eval_limited(&format!("{}(spin 60)", spin), limits) {
+    Err(error::HarpError::LimitExceeded(msg)) => assert!(msg.contains("depth")),
+    res => panic!("Expected the depth limit to be hit, got {:?}", res),
+  }
+
+  let limits = limits::Limits {
+    max_values: Some(10),
+    ..limits
+  };
+  assert!(matches!(
+    eval_limited("(def xs '(1 2 3 4 5 6 7 8 9 10))", limits),
+    Err(error::HarpError::LimitExceeded(_))
+  ));
+}
+
+#[test]
+fn lazy_limits_test() {
+  let mut env = crate::common::prelude::make_std_env();
+  let eval = |code: &str, env: &mut EnvHead| {
+    quick_eval::qeval_progn(&crate::reader::reader::Reader::new(code).next_progn().unwrap(), env)
+  };
+  eval("(defun naturals (n) (lazy-seq (cons n (naturals (+ n 1))))) (def xs (naturals 0))", &mut env).unwrap();
+
+  // Running out of steps while realizing is reported as such, not as the index being out of bounds
+  env.budget().set_limits(limits::Limits {
+    max_steps: Some(500),
+    ..limits::Limits::default()
+  });
+  env.budget().reset();
+  match eval("(nth xs 1000)", &mut env) {
+    Err(error::HarpError::LimitExceeded(msg)) => assert!(msg.contains("500 steps")),
+    res => panic!("Expected the step limit to be hit, got {:?}", res),
+  }
+
+  // and doesn't break the sequence for the next evaluation
+  env.budget().set_limits(limits::Limits::default());
+  env.budget().reset();
+  assert_eq!(eval("(nth xs 1000)", &mut env), Ok(Value::Number(1000.0)));
+}
+
+#[test]
+fn limits_are_catchable_test() {
+  let limits = limits::Limits {
+    max_steps: None,
+    max_depth: Some(20),
+    max_values: None,
+  };
+  let res = eval_limited(
+    "(defun forever (n) (forever n)) (try (forever 1) (catch e (print e) \"caught\"))",
+    limits,
+  );
+  assert_eq!(res, Ok(Value::String("caught".to_string())));
+}
+
+#[test]
+fn interrupt_test() {
+  let mut env = crate::common::prelude::make_std_env();
+  env.budget().reset();
+  env
+    .budget()
+    .interrupt_flag()
+    .store(true, std::sync::atomic::Ordering::SeqCst);
+
+  let progn = crate::reader::reader::Reader::new("(try (+ 1 2) (catch e 0))")
+    .next_progn()
+    .unwrap();
+  assert_eq!(
+    quick_eval::qeval_progn(&progn, &mut env),
+    Err(error::HarpError::Interrupted)
+  );
+
+  // A reset clears the interrupt for the next evaluation
+  env.budget().reset();
+  assert_eq!(quick_eval::qeval_progn(&progn, &mut env), Ok(Value::Number(3.0)));
+}
+
+#[cfg(test)]
+fn eval_both(code: &str, setup: fn(&mut EnvHead)) -> Vec<error::EvalResult> {
+  use crate::translator::translator::Translator;
+
+  let read = || crate::reader::reader::Reader::new(code).next_progn().unwrap();
+  let progn = read();
+  let mut quick_env = crate::common::prelude::make_std_env();
+  setup(&mut quick_env);
+  let mut vm_env = crate::common::prelude::make_std_env();
+  setup(&mut vm_env);
+
+  let script = Translator::for_env(OptLevel::O0, &vm_env).progn_to_script(read());
+  vec![
+    quick_eval::qeval_progn(&progn, &mut quick_env),
+    vm::Vm::new().eval_script(&mut vm_env, &script),
+  ]
+}
+
+// Runs each case through both evaluators and compares how the results print
+#[cfg(test)]
+fn assert_cases(cases: &[(&str, &str)]) {
+  assert_cases_after("", cases);
+}
+
+// The same, with definitions the cases share evaluated before each of them
+#[cfg(test)]
+fn assert_cases_after(setup: &str, cases: &[(&str, &str)]) {
+  for (code, expected) in cases {
+    for res in eval_both(&format!("{} {}", setup, code), |_| {}) {
+      assert_eq!(format!("{:?}", res), *expected, "{}", code);
+    }
+  }
+}
+
+#[cfg(test)]
+fn register_tick(env: &mut EnvHead) {
+  use std::{cell::Cell, rc::Rc};
+
+  let ticks = Rc::new(Cell::new(0.0));
+  env.register("tick", move || {
+    ticks.set(ticks.get() + 1.0);
+    ticks.get()
+  });
+  env.set(
+    "unless".to_string(),
+    Value::NativeFunc(native::Native::special(
+      "unless",
+      native::Arity::exact(2),
+      |args, env| match quick_eval::qeval_value(args[0].clone(), env)? {
+        Value::Bool(false) => quick_eval::qeval_value(args[1].clone(), env),
+        _ => Ok(Value::Unit),
+      },
+    )),
+  );
+}
+
+#[test]
+fn calling_convention_test() {
+  // Arguments are evaluated once each, from left to right
+  for res in eval_both("(- (tick) (tick) (tick))", register_tick) {
+    assert_eq!(res, Ok(Value::Number(-4.0)));
+  }
+  for res in eval_both("(eq (tick) (tick) (tick)) (tick)", register_tick) {
+    assert_eq!(res, Ok(Value::Number(4.0)));
+  }
+  // Special forms get the syntax and pick what to evaluate
+  for res in eval_both("(unless #t (tick)) (unless #f (tick)) (tick)", register_tick) {
+    assert_eq!(res, Ok(Value::Number(2.0)));
+  }
+  for res in eval_both("(def x 'sym) (quote x)", register_tick) {
+    assert_eq!(res, Ok(Value::Atom("x".to_string())));
+  }
+}
+
+#[test]
+fn special_forms_test() {
+  let env = crate::common::prelude::make_std_env();
+  for name in ["if", "quote", "try", "def", "set!", "lambda", "defun", "+", "eq", "print"].iter() {
+    match env.get(name.to_string()) {
+      Some(Value::NativeFunc(native)) => {
+        assert_eq!(native.special, crate::common::prelude::is_special_form(name), "{}", name)
+      }
+      v => panic!("{} is {:?}", name, v),
+    }
+  }
+}
+
+#[test]
+fn params_test() {
+  let cases = [
+    ("(defun f (a &optional (b (* a 2))) (+ a b)) (f 3)", Ok(Value::Number(9.0))),
+    ("(defun f (a &rest xs) xs) (f 1 2 3)", Ok(Value::list(vec![Value::Number(2.0), Value::Number(3.0)]))),
+    ("(defun f (&key (x 1) (y 2)) (- x y)) (f :y 5)", Ok(Value::Number(-4.0))),
+    (
+      "(defun f (a b) a) (f 1)",
+      Err(error::HarpError::Runtime("f expected 2 arguments, but got 1".to_string())),
+    ),
+    (
+      "((lambda (a &optional b) a) 1 2 3)",
+      Err(error::HarpError::Runtime("anon expected 1 to 2 arguments, but got 3".to_string())),
+    ),
+    (
+      "(defun f (a &rest xs) a) (f)",
+      Err(error::HarpError::Runtime("f expected at least 1 arguments, but got 0".to_string())),
+    ),
+    (
+      "(defun f (&key x) x) (f :z 1)",
+      Err(error::HarpError::Runtime("f got an unknown keyword argument :z".to_string())),
+    ),
+    (
+      "(defun f (&key x) x) (f :x)",
+      Err(error::HarpError::Runtime("f expected keyword arguments in pairs".to_string())),
+    ),
+    (
+      "(defun f (a &rest) a)",
+      Err(error::HarpError::Runtime("Defun expected a name after &rest".to_string())),
+    ),
+  ];
+
+  for (code, expected) in cases.iter() {
+    for res in eval_both(code, |_| {}) {
+      match (&res, expected) {
+        (Ok(Value::Func(_, _, _)), _) => {}
+        _ => assert_eq!(&res, expected, "{}", code),
+      }
+    }
+  }
+}
+
+#[test]
+fn destructuring_test() {
+  let cases = [
+    ("(let [[a b & xs] [1 2 3 4]] xs)", "Ok(List(3 4))"),
+    ("(let [{:keys [x y] :as p} {:x 1 :y 2}] (+ x y))", "Ok(3)"),
+    ("(let [{n :name} {:name \"orc\"} m n] m)", "Ok(orc)"),
+    ("(let [[a [b c]] [1 [2]]] c)", "Ok(())"),
+    ("(defun f ([a b] {:keys [c]}) (* a b c)) (f [2 3] {:c 4})", "Ok(24)"),
+    ("(doseq [[k v] {:a 1 :b 2}] (print k v))", "Ok(())"),
+    ("(let [[a] 5] a)", "Err(Runtime(\"Cannot destructure 5 with [a]\"))"),
+    ("(let [a] a)", "Err(Runtime(\"Let expected a value for every pattern\"))"),
+    ("(let [[a & b c] '(1)] a)", "Err(Runtime(\"Unexpected c after the rest pattern in [a & b c]\"))"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn match_test() {
+  let cases = [
+    ("(match 2 1 \"one\" 2 \"two\" _ \"many\")", "Ok(two)"),
+    ("(match :left :up 1 _ 0)", "Ok(0)"),
+    ("(match '(:go 3) (:go n) (* n 2))", "Ok(6)"),
+    ("(match [1 2 3] [a & more] more)", "Ok(List(2 3))"),
+    ("(match {:hp 0 :name \"orc\"} {:hp 0 :name n} n)", "Ok(orc)"),
+    ("(match 5 n :when (eq n 4) :four n :when (eq n 5) :five)", "Ok(:five)"),
+    ("(match '(1 2) [a b] :vector (a b) :list)", "Ok(:list)"),
+    ("(match 3 1 :one)", "Err(Runtime(\"No match clause matched 3\"))"),
+    ("(match 3 1)", "Err(Runtime(\"Match expected a body after the pattern 1\"))"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn record_test() {
+  let setup = "(defrecord Item [name weight]) (def lamp (make-item \"lamp\" 2)) ";
+  let cases = [
+    ("lamp", "Ok(#Item{:name lamp :weight 2})"),
+    ("(item-weight (assoc lamp :weight 3))", "Ok(3)"),
+    ("(item-weight lamp)", "Ok(2)"),
+    ("(eq lamp (make-item \"lamp\" 2))", "Ok(#t)"),
+    ("(eq lamp (assoc lamp :weight 1))", "Ok(#f)"),
+    ("(item? lamp)", "Ok(#t)"),
+    ("(item? {:name \"lamp\" :weight 2})", "Ok(#f)"),
+    ("(get lamp :name)", "Ok(lamp)"),
+    ("(match lamp {:weight w} w)", "Ok(2)"),
+    ("(defrecord Item [name weight]) (item? lamp)", "Ok(#f)"),
+    ("(make-item 1)", "Err(Runtime(\"make-item expected 2 arguments, but got 1\"))"),
+    ("(assoc lamp :color 1)", "Err(Runtime(\"Item has no field :color\"))"),
+    ("(defrecord Bad [a a])", "Err(Runtime(\"Defrecord expected distinct field names, but got a\"))"),
+  ];
+
+  assert_cases_after(setup, &cases);
+}
+
+#[test]
+fn deftype_test() {
+  let setup = "(deftype Shape (Circle r) (Rect w h) Empty) (def c (Circle 2)) ";
+  let cases = [
+    ("c", "Ok(Circle(2))"),
+    ("Empty", "Ok(Empty)"),
+    ("(Rect 1 3)", "Ok(Rect(1 3))"),
+    ("(shape? c)", "Ok(#t)"),
+    ("(circle? c)", "Ok(#t)"),
+    ("(rect? c)", "Ok(#f)"),
+    ("(empty? Empty)", "Ok(#t)"),
+    ("(shape? '(Circle 2))", "Ok(#f)"),
+    ("(eq c (Circle 2))", "Ok(#t)"),
+    ("(eq c (Circle 3))", "Ok(#f)"),
+    ("(get (Rect 1 3) :h)", "Ok(3)"),
+    ("(match (Rect 2 5) (Circle r) r (Rect w h) (* w h) Empty 0)", "Ok(10)"),
+    ("(match Empty (Circle r) r Empty :empty)", "Ok(:empty)"),
+    ("(match c (Circle 1) :small (Circle r) :when (eq r 2) :two _ :other)", "Ok(:two)"),
+    ("(match '(Circle 2) (Circle r) r _ :list)", "Ok(:list)"),
+    ("(deftype Shape (Circle r)) (shape? c)", "Ok(#f)"),
+    ("(Circle)", "Err(Runtime(\"Circle expected 1 arguments, but got 0\"))"),
+    ("(deftype Bad (circle r))", "Err(Runtime(\"Deftype expected variant names to start with an uppercase letter, but got circle\"))"),
+    ("(deftype Bad A (A x))", "Err(Runtime(\"Deftype Bad has two variants named A\"))"),
+    ("(deftype Bad (A x x))", "Err(Runtime(\"Deftype expected distinct field names, but got x\"))"),
+  ];
+
+  assert_cases_after(setup, &cases);
+}
+
+#[test]
+fn generic_test() {
+  let setup = "(defrecord V [x y]) (deftype Shape (Circle r) (Rect w h)) (defgeneric area (shape)) \
+    (defmethod area ((c Circle)) (* 3 (get c :r) (get c :r))) (defmethod area ((s Shape)) 0) ";
+  let cases = [
+    ("(area (Circle 2))", "Ok(12)"),
+    ("(area (Rect 1 2))", "Ok(0)"),
+    ("(defmethod area (x) :default) (area 5)", "Ok(:default)"),
+    ("(defmethod area ((s Shape)) 1) (area (Rect 1 2))", "Ok(1)"),
+    ("(defmethod + ((a V) (b V)) (make-v (+ (v-x a) (v-x b)) (+ (v-y a) (v-y b)))) (+ (make-v 1 2) (make-v 3 4))", "Ok(#V{:x 4 :y 6})"),
+    ("(defmethod + ((a V) (b V)) 0) (+ 1 2 3)", "Ok(6)"),
+    ("(defmethod eq ((a V) (b V)) (eq (v-x a) (v-x b))) (eq (make-v 1 2) (make-v 1 3))", "Ok(#t)"),
+    ("(defmethod show ((v V)) \"a vector\") (show (make-v 1 2))", "Ok(a vector)"),
+    ("(defun f (x) :plain) (defmethod f ((x Number)) :number) (f \"a\")", "Ok(:plain)"),
+    ("[(type-of 1) (type-of :a) (type-of 'a) (type-of (make-v 1 2)) (type-of (Circle 1))]", "Ok([Number Keyword Atom V Shape])"),
+    ("(area 5)", "Err(Runtime(\"area has no method for (Number)\"))"),
+    ("(area)", "Err(Runtime(\"area expected 1 arguments, but got 0\"))"),
+    ("(+ (make-v 1 2) 1)", "Err(Runtime(\"'+' can only be used with numbers\"))"),
+    ("(defmethod if (x) 1)", "Err(Runtime(\"if is a special form and can't have methods\"))"),
+    ("(defmethod area ((c)) 1)", "Err(Runtime(\"Defmethod expected (param Type), but got List(c)\"))"),
+  ];
+
+  assert_cases_after(setup, &cases);
+}
+
+#[test]
+fn equality_test() {
+  let cases = [
+    ("(eq '(1 2) '(1 2))", "Ok(#t)"),
+    ("(eq '() '())", "Ok(#t)"),
+    ("(eq [1 [2 3]] [1 [2 3]])", "Ok(#t)"),
+    ("(eq [1 2] '(1 2))", "Ok(#f)"),
+    ("(eq {:a 1 :b 2} {:b 2 :a 1})", "Ok(#t)"),
+    ("(eq {:a 1} {:a 2})", "Ok(#f)"),
+    ("(equal? '(1 (2)) '(1 (2)))", "Ok(#t)"),
+    ("(eq? '(1 (2)) '(1 (2)))", "Ok(#t)"),
+    ("(defun f () 1) (eq f f)", "Ok(#t)"),
+    ("(eq (lambda () 1) (lambda () 1))", "Ok(#f)"),
+    ("(eq + +)", "Ok(#t)"),
+    ("(= 1 1 1)", "Ok(#t)"),
+    ("(= 1 2)", "Ok(#f)"),
+    ("(= 1 :a)", "Err(Runtime(\"= expected numbers, but got :a\"))"),
+    ("(compare 1 2)", "Ok(-1)"),
+    ("(compare \"b\" \"a\")", "Ok(1)"),
+    ("(compare [1 2] [1 2])", "Ok(0)"),
+    ("(compare 5 \"a\")", "Ok(-1)"),
+    ("(match '(look) '(look) :look _ :other)", "Ok(:look)"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn value_hash_ord_test() {
+  use std::collections::HashSet;
+
+  let list = |xs: &[f64]| Value::List(xs.iter().map(|x| Value::Number(*x)).collect());
+  let map = |entries: &[(&str, f64)]| {
+    Value::Map(entries.iter().map(|(k, v)| (Value::Atom(k.to_string()), Value::Number(*v))).collect())
+  };
+
+  let mut set = HashSet::new();
+  set.insert(list(&[1.0, 2.0]));
+  set.insert(map(&[(":a", 1.0), (":b", 2.0)]));
+  set.insert(Value::Number(0.0));
+  assert!(set.contains(&list(&[1.0, 2.0])));
+  assert!(set.contains(&map(&[(":b", 2.0), (":a", 1.0)])));
+  assert!(set.contains(&Value::Number(-0.0)));
+  assert!(!set.contains(&Value::vector(vec![Value::Number(1.0), Value::Number(2.0)])));
+  assert_eq!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+
+  let mut values = vec![
+    Value::String("b".to_string()),
+    list(&[2.0]),
+    Value::Number(3.0),
+    Value::Unit,
+    Value::Number(f64::NAN),
+    list(&[1.0, 5.0]),
+    Value::Bool(true),
+    Value::Number(-1.0),
+  ];
+  values.sort();
+  assert_eq!(format!("{:?}", values), "[(), #t, -1, 3, NaN, b, List(1 5), List(2)]");
+}
+
+#[test]
+fn collections_test() {
+  let cases = [
+    ("(cons 1 '(2 3))", "Ok(List(1 2 3))"),
+    ("(cons 1 '())", "Ok(List(1))"),
+    ("(def xs '(2 3)) (cons 1 xs) xs", "Ok(List(2 3))"),
+    ("(cons 1 [2])", "Err(Runtime(\"Cons expected a list or a lazy sequence, but got [2]\"))"),
+    ("(conj '(2 3) 1 0)", "Ok(List(0 1 2 3))"),
+    ("(conj [1 2] 3 4)", "Ok([1 2 3 4])"),
+    ("(conj {:a 1} [:b 2])", "Ok({:a 1 :b 2})"),
+    ("(conj {:a 1} :b)", "Err(Runtime(\"Conj expected a [key value] entry for a map, but got :b\"))"),
+    ("(def v [1 2]) (assoc v 0 :x 2 3)", "Ok([:x 2 3])"),
+    ("(def v [1 2]) (assoc v 0 :x) v", "Ok([1 2])"),
+    ("(assoc [1 2] 5 0)", "Err(Runtime(\"Assoc can't set index 5 of a vector of 2\"))"),
+    ("(assoc [] -1 :x)", "Err(Runtime(\"Assoc can't set index -1 of a vector of 0\"))"),
+    ("(def m {:a 1}) (assoc m :b 2) m", "Ok({:a 1})"),
+    ("{:c 3 :a 1 :b 2}", "Ok({:a 1 :b 2 :c 3})"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn persistent_list_test() {
+  use collections::List;
+
+  let tail: List = (0..3).map(|n| Value::Number(n as f64)).collect();
+  let list = tail.cons(Value::Number(-1.0));
+  assert_eq!(list.len(), 4);
+  assert_eq!(list.rest(), tail);
+  assert_eq!(tail.len(), 3);
+
+  // Dropping a long list doesn't recurse once per cell
+  let long = (0..200_000).fold(List::new(), |list, n| list.cons(Value::Number(n as f64)));
+  assert_eq!(long.skip(199_999).first(), Some(&Value::Number(0.0)));
+  drop(long);
+}
+
+#[test]
+fn sequence_test() {
+  let cases = [
+    ("(list 1 2 3)", "Ok(List(1 2 3))"),
+    ("(car '(1 2)) (cdr '(1 2 3))", "Ok(List(2 3))"),
+    ("(first [])", "Ok(())"),
+    ("(rest \"abc\")", "Ok(bc)"),
+    ("(range 3)", "Ok(List(0 1 2))"),
+    ("(range 5 0 -2)", "Ok(List(5 3 1))"),
+    ("(range 0 1 0)", "Err(Runtime(\"Range expected a non-zero step\"))"),
+    ("(nth [1 2 3] 1)", "Ok(2)"),
+    ("(nth '(1) 4 :none)", "Ok(:none)"),
+    ("(nth \"ab\" 2)", "Err(Runtime(\"Nth index 2 is out of bounds for ab\"))"),
+    ("(length \"héllo\")", "Ok(5)"),
+    ("(map (lambda (x) (* x x)) [1 2 3])", "Ok(List(1 4 9))"),
+    ("(map + '(1 2 3) [10 20])", "Ok(List(11 22))"),
+    ("(filter (lambda (x) (eq x \"a\")) \"banana\")", "Ok(aaa)"),
+    ("(filter (lambda (x) x) [1])", "Err(Runtime(\"Filter expected its predicate to return a boolean, but got 1\"))"),
+    ("(reduce + 0 [1 2 3])", "Ok(6)"),
+    ("(reduce * [2 3 4])", "Ok(24)"),
+    ("(for-each (lambda (x) x) [1 2])", "Ok(())"),
+    ("(for-each (lambda (x) (error x)) [1 2])", "Err(Runtime(\"1\"))"),
+    ("(sort [3 1 2])", "Ok([1 2 3])"),
+    ("(sort '(1 3 2) (lambda (a b) (eq (compare a b) 1)))", "Ok(List(3 2 1))"),
+    ("(sort [[1 :b] [0 :a] [1 :a]] (lambda (x y) (compare (first x) (first y))))", "Ok([[0 :a] [1 :b] [1 :a]])"),
+    ("(sort \"cab\")", "Ok(abc)"),
+    ("(zip [1 2 3] '(a b))", "Ok(List([1 a] [2 b]))"),
+    ("(take 2 [1 2 3])", "Ok([1 2])"),
+    ("(drop 1 '(1 2 3))", "Ok(List(2 3))"),
+    ("(take -1 [1])", "Err(Runtime(\"Take expected a non-negative integer, but got -1\"))"),
+    ("(reverse \"abc\")", "Ok(cba)"),
+    ("(append [1] '(2) \"c\")", "Ok([1 2 c])"),
+    ("(map first 5)", "Err(Runtime(\"Map expected a sequence, but got 5\"))"),
+    ("(second [1 2 3])", "Ok(2)"),
+    ("(last '(1 2 3))", "Ok(3)"),
+    ("(empty? \"\")", "Ok(#t)"),
+    ("(remove (lambda (x) (eq x 2)) [1 2 3])", "Ok([1 3])"),
+    ("(sum (range 5))", "Ok(10)"),
+    ("(mapcat (lambda (x) [x x]) [1 2])", "Ok(List(1 1 2 2))"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn lazy_test() {
+  let naturals = "(defun naturals (n) (lazy-seq (cons n (naturals (+ n 1)))))";
+  let cases = [
+    ("(doall (take 3 (naturals 0)))", "Ok(Lazy(0 1 2))"),
+    ("(take 2 (naturals 0))", "Ok(Lazy(...))"),
+    ("(def xs (naturals 0)) (nth xs 2) xs", "Ok(Lazy(0 1 2 ...))"),
+    ("(first (rest (naturals 5)))", "Ok(6)"),
+    ("(nth (iterate (lambda (x) (* x 2)) 1) 10)", "Ok(1024)"),
+    ("(doall (take 5 (cycle [1 2])))", "Ok(Lazy(1 2 1 2 1))"),
+    ("(reduce + (take 4 (repeat 3)))", "Ok(12)"),
+    ("(repeat 2 :a)", "Ok(List(:a :a))"),
+    ("(doall (take 3 (filter (lambda (n) (eq (compare n 10) 1)) (naturals 0))))", "Ok(Lazy(11 12 13))"),
+    ("(doall (map + (naturals 0) [10 20]))", "Ok(Lazy(10 21))"),
+    ("(doall (zip (naturals 0) \"ab\"))", "Ok(Lazy([0 a] [1 b]))"),
+    ("(first (drop 100 (naturals 0)))", "Ok(100)"),
+    // Every element is made by a call in the scope of the one before
+    ("(nth (naturals 0) 5000)", "Ok(5000)"),
+    ("(defun countdown (n) (let [m (- n 1)] (lazy-seq (cons n (countdown m))))) (nth (countdown 0) 3000)", "Ok(-3000)"),
+    ("(doall (lazy-seq [1 2]))", "Ok(Lazy(1 2))"),
+    ("(empty? (lazy-seq '()))", "Ok(#t)"),
+    ("(any? (lambda (n) (eq n 7)) (naturals 0))", "Ok(#t)"),
+    ("(sort (take 3 (iterate (lambda (x) (- x 1)) 0)))", "Ok(List(-2 -1 0))"),
+    ("(doall (cycle []))", "Ok(Lazy())"),
+    ("(first (lazy-seq 5))", "Err(Runtime(\"Lazy-seq expected its body to return a sequence, but got 5\"))"),
+    ("(def s (lazy-seq (error \"boom\"))) (try (first s) (catch e e)) (first s)", "Err(Runtime(\"boom\"))"),
+    ("(type-of (repeat 1))", "Ok(Lazy)"),
+  ];
+
+  assert_cases_after(naturals, &cases);
+}
+
+#[test]
+fn string_test() {
+  let cases = [
+    ("(str/split \"a,b,,c\" \",\")", "Ok(List(a b  c))"),
+    ("(length (str/split \"  go  north \"))", "Ok(2)"),
+    ("(str/split \"né\" \"\")", "Ok(List(n é))"),
+    ("(str/join [\"a\" 1 :b] \"-\")", "Ok(a-1-:b)"),
+    ("(str/join (take 3 (repeat \"ab\")))", "Ok(ababab)"),
+    ("(str/trim \"  hi \")", "Ok(hi)"),
+    ("(str/upper \"straße\")", "Ok(STRASSE)"),
+    ("(str/lower \"ÉCOLE\")", "Ok(école)"),
+    ("(str/contains? \"dragon\" \"rag\")", "Ok(#t)"),
+    ("(str/index-of \"héllo\" \"l\")", "Ok(2)"),
+    ("(str/index-of \"hello\" \"z\")", "Ok(())"),
+    ("(str/replace \"a-b-c\" \"-\" \"+\")", "Ok(a+b+c)"),
+    ("(str/substring \"héllo\" 1 3)", "Ok(él)"),
+    ("(str/substring \"héllo\" 2)", "Ok(llo)"),
+    ("(str/substring \"abc\" 2 9)", "Err(Runtime(\"str/substring range 2..9 is out of bounds for a string of 3 characters\"))"),
+    ("(str/starts-with? \"north\" \"no\")", "Ok(#t)"),
+    ("(str/ends-with? \"north\" \"no\")", "Ok(#f)"),
+    ("(str/length \"日本語\")", "Ok(3)"),
+    ("(str/to-number \" 4.5 \")", "Ok(4.5)"),
+    ("(str/to-number \"four\")", "Ok(())"),
+    ("(str/from-number 12)", "Ok(12)"),
+    ("(str/upper 5)", "Err(Runtime(\"str/upper argument 1 expected a string, but got 5\"))"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn format_test() {
+  let cases = [
+    ("(format \"{} has {} hp\" \"Bob\" 12)", "Ok(Bob has 12 hp)"),
+    ("(format \"{1} {0} {1}\" :a :b)", "Ok(:b :a :b)"),
+    ("(format \"{name} the {class}\" {:name \"Ana\" :class \"bard\"})", "Ok(Ana the bard)"),
+    ("(defrecord Pos [x y]) (format \"at {x},{y}\" (make-pos 1 2))", "Ok(at 1,2)"),
+    ("(format \"[{:>5}] [{:<4}] [{:^7}]\" 42 \"ab\" \"mid\")", "Ok([   42] [ab  ] [  mid  ])"),
+    ("(format \"{:*^9}\" \"x\")", "Ok(****x****)"),
+    ("(format \"{:.2} {:06.1} {:.3}\" 3.14159 -2.5 \"abcdef\")", "Ok(3.14 -002.5 abc)"),
+    ("(format \"{{{}}}\" [1 2])", "Ok({[1 2]})"),
+    ("(format \"{} {}\" 1)", "Err(Runtime(\"Format expected an argument for placeholder 2\"))"),
+    ("(format \"{nope}\" {:a 1})", "Err(Runtime(\"Format has no value for {nope}\"))"),
+    ("(format \"{:x}\" 1)", "Err(Runtime(\"Format can't read the spec {:x}\"))"),
+    ("(format \"{\" 1)", "Err(Runtime(\"Format expected } to close {\"))"),
+    ("(str \"a\" 1 :b [2])", "Ok(a1:b[2])"),
+    ("(def name \"Zed\") (def hp 3) #\"Hello {name}, {(+ hp 1)} hp {{ok}}\"", "Ok(Hello Zed, 4 hp {ok})"),
+    ("#\"\"", "Ok()"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn regex_test() {
+  let cases = [
+    ("(re/match #r\"go (\\w+)\" \"go north\")", "Ok([go north north])"),
+    ("(re/match #r\"go (\\w+)\" \"let's go north\")", "Ok(())"),
+    ("(re/find #r\"go (\\w+)\" \"let's go north\")", "Ok([go north north])"),
+    ("(re/match #r\"(?P<verb>take|drop) (the )?(?P<item>\\w+)\" \"take the lamp\")", "Ok({:item lamp :verb take})"),
+    ("(re/match \"a|ab\" \"ab\")", "Ok(ab)"),
+    ("(re/find-all #r\"\\d+\" \"3 rats, 12 bats\")", "Ok(List(3 12))"),
+    ("(re/find-all #r\"(\\w)(\\d)?\" \"a1b\")", "Ok(List([a1 a 1] [b b ()]))"),
+    ("(re/replace #r\"(\\w+)@(\\w+)\" \"ann@home\" \"$2:$1\")", "Ok(home:ann)"),
+    ("(re/split #r\"\\s*,\\s*\" \"a , b,c\")", "Ok(List(a b c))"),
+    ("(eq (re/compile \"x+\") #r\"x+\")", "Ok(#t)"),
+    ("(type-of #r\"x\")", "Ok(Regex)"),
+    ("#r\"[a-z]\"", "Ok(#r\"[a-z]\")"),
+    ("(re/find 5 \"x\")", "Err(Runtime(\"re/find expected a regular expression, but got 5\"))"),
+  ];
+
+  assert_cases(&cases);
+
+  assert!(matches!(eval_both("(re/compile \"(\")", |_| {})[0], Err(error::HarpError::Runtime(_))));
+}
+
+#[test]
+fn math_test() {
+  let cases = [
+    ("(/ 12 2 3)", "Ok(2)"),
+    ("(/ 4)", "Ok(0.25)"),
+    ("(/ 1 0)", "Err(Runtime(\"/ can't divide 1 by zero\"))"),
+    ("(/ 1 \"2\")", "Err(Runtime(\"/ expected numbers, but got 2\"))"),
+    ("(list (mod 7 3) (mod -1 3) (mod 1 -3))", "Ok(List(1 2 -2))"),
+    ("(mod 1 0)", "Err(Runtime(\"mod can't divide 1 by zero\"))"),
+    ("(list (< 1 2 3) (< 1 3 2) (> 3 2 1) (<= 1 1 2) (>= 2 2 3) (< 1))", "Ok(List(#t #f #t #t #f #t))"),
+    ("(< 1 'a)", "Err(Runtime(\"< expected numbers, but got a\"))"),
+    ("(list (min 3 1 2) (max 3 1 2) (abs -4))", "Ok(List(1 3 4))"),
+    ("(list (floor -1.5) (ceil 1.2) (round 2.5) (round -2.5) (trunc -1.7))", "Ok(List(-2 2 3 -3 -1))"),
+    ("(list (sqrt 16) (pow 2 10) (log 8 2) (log 1))", "Ok(List(4 1024 3 0))"),
+    ("(sqrt -1)", "Err(Runtime(\"sqrt expected a non-negative number, but got -1\"))"),
+    ("(log 0)", "Err(Runtime(\"log expected a positive number, but got 0\"))"),
+    ("(list (sin 0) (cos 0) (round (* 2 (atan2 1 1) (/ 2 math/pi))))", "Ok(List(0 1 1))"),
+    ("(floor \"x\")", "Err(Runtime(\"floor argument 1 expected a number, but got x\"))"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn random_test() {
+  let cases = [
+    // The same seed gives the same numbers, in both evaluators
+    ("(rand/seed 7) (def a (rand/int 1000)) (rand/seed 7) (= a (rand/int 1000))", "Ok(#t)"),
+    ("(def g (rand/new 3)) (def h (rand/new 3)) (= (rand/float g) (rand/float h))", "Ok(#t)"),
+    // and the same ones on every platform and release
+    ("(rand/seed 7) [(rand/int 1000) (rand/int 1000) (rand/int 1000)]", "Ok([157 704 726])"),
+    ("(every? (lambda (n) (<= 2 n 4)) (map (lambda (_) (rand/int 2 5)) (range 50)))", "Ok(#t)"),
+    ("(def x (rand/float -1 1)) (list (<= -1 x) (< x 1))", "Ok(List(#t #t))"),
+    ("(sort (rand/shuffle [3 1 4 1 5]))", "Ok([1 1 3 4 5])"),
+    ("(type-of (rand/shuffle '(1 2)))", "Ok(List)"),
+    ("(rand/choice [:only])", "Ok(:only)"),
+    ("(rand/weighted-choice {:never 0 :always 2})", "Ok(:always)"),
+    ("(rand/weighted-choice (rand/new 1) [[:a 0] [:b 1]])", "Ok(:b)"),
+    ("(rand/int 3 3)", "Err(Runtime(\"rand/int expected a non-empty range, but got 3..3\"))"),
+    ("(rand/int 2.5)", "Err(Runtime(\"rand/int expected an integer, but got 2.5\"))"),
+    ("(rand/choice [])", "Err(Runtime(\"rand/choice expected a non-empty sequence\"))"),
+    ("(rand/choice 1 [2])", "Err(Runtime(\"rand/choice expected a generator, but got 1\"))"),
+    ("(rand/weighted-choice {:a 0})", "Err(Runtime(\"rand/weighted-choice expected some weight above zero\"))"),
+    ("(rand/weighted-choice [[:a -1]])", "Err(Runtime(\"rand/weighted-choice expected a non-negative weight, but got -1\"))"),
+    ("(rand/new)", "Ok(#<Random>)"),
+  ];
+
+  assert_cases(&cases);
+
+  // A seeded run repeats itself
+  let draws = |seed| {
+    let mut env = crate::common::prelude::make_std_env();
+    env.seed_random(seed);
+    let progn = crate::reader::reader::Reader::new("(map (lambda (_) (rand/int 100)) (range 10))").next_progn();
+    quick_eval::qeval_progn(&progn.unwrap(), &mut env).unwrap()
+  };
+  assert_eq!(draws(42), draws(42));
+  assert_ne!(draws(42), draws(43));
+}
+
+#[test]
+fn integer_test() {
+  let cases = [
+    ("(list (quot 7 2) (quot -7 2) (rem 7 2) (rem -7 2) (mod -7 2) (mod 7 -2))", "Ok(List(3 -3 1 -1 1 -1))"),
+    ("(quot 1 0)", "Err(Runtime(\"quot can't divide 1 by zero\"))"),
+    ("(mod 5.5 2)", "Err(Runtime(\"mod expected an integer, but got 5.5\"))"),
+    ("(rem 4 'a)", "Err(Runtime(\"rem expected an integer, but got a\"))"),
+    ("(quot 9223372036854775808 1)", "Err(Runtime(\"quot expected an integer, but got 9223372036854776000\"))"),
+    ("(list (bit-and 12 10) (bit-or 12 10) (bit-xor 12 10) (bit-and 7 6 4))", "Ok(List(8 14 6 4))"),
+    ("(list (bit-not 0) (bit-not 5))", "Ok(List(-1 -6))"),
+    ("(list (bit-shift-left 1 10) (bit-shift-right 1024 3) (bit-shift-right -16 2))", "Ok(List(1024 128 -4))"),
+    ("(list (popcount 255) (popcount 0) (popcount -1))", "Ok(List(8 0 64))"),
+    ("(bit-and 1.5 1)", "Err(Runtime(\"bit-and expected an integer, but got 1.5\"))"),
+    ("(bit-shift-left 1 64)", "Err(Runtime(\"bit-shift-left expected a shift from 0 to 63, but got 64\"))"),
+    ("(bit-not 0.5)", "Err(Runtime(\"bit-not expected an integer, but got 0.5\"))"),
+  ];
+
+  assert_cases(&cases);
+}
+
+#[test]
+fn fs_test() {
+  use std::path::{Path, PathBuf};
+
+  // Removes the test's directory however the test ends
+  struct TempDir(PathBuf);
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      std::fs::remove_dir_all(&self.0).ok();
+    }
+  }
+
+  let temp = TempDir(std::env::temp_dir().join(format!("harp-fs-test-{}", std::process::id())));
+  let dir = temp.0.to_string_lossy();
+  let run = |code: String| {
+    let mut env = crate::common::prelude::make_std_env();
+    let progn = crate::reader::reader::Reader::new(&code).next_progn().unwrap();
+    format!("{:?}", quick_eval::qeval_progn(&progn, &mut env))
+  };
+
+  let code = format!(
+    "(def dir (fs/join \"{}\" \"saves\"))
+     (fs/mkdir dir)
+     (def save (fs/join dir \"slot1.txt\"))
+     (fs/write-string save \"hp 10\")
+     (fs/append save \" and more\")
+     (fs/append (fs/join dir \"log.txt\") \"a\")
+     (list (fs/read-string save) (fs/read-lines save) (fs/list-dir dir) (fs/exists? save))",
+    dir
+  );
+  assert_eq!(run(code), "Ok(List(hp 10 and more List(hp 10 and more) List(log.txt slot1.txt) #t))");
+
+  let code = format!(
+    "(def dir (fs/join \"{}\" \"saves\"))
+     (try (fs/remove dir) (catch e e))
+     (fs/remove (fs/join dir \"slot1.txt\"))
+     (fs/remove (fs/join dir \"log.txt\"))
+     (fs/remove dir)
+     (list (fs/exists? dir) (try (fs/read-string (fs/join dir \"slot1.txt\")) (catch e \"missing\")))",
+    dir
+  );
+  assert_eq!(run(code), "Ok(List(#f missing))");
+
+  // Paths come back with the platform's separators
+  let path = |path: PathBuf| format!("Ok({})", path.display());
+  let root = Path::new("/");
+  let cases = [
+    ("(fs/join \"a\" \"b\" \"c.txt\")", path(Path::new("a").join("b").join("c.txt"))),
+    ("(fs/join \"a\" \"/etc\")", path(root.join("etc"))),
+    ("(fs/normalize \"saves/../maps/./a.map\")", path(Path::new("maps").join("a.map"))),
+    ("(fs/normalize \"../a/../../b\")", path(Path::new("..").join("..").join("b"))),
+    ("(fs/normalize \"/../x/.\")", path(root.join("x"))),
+    ("(fs/normalize \"a/..\")", path(PathBuf::from("."))),
+    ("(fs/join \"a\" 1)", "Err(Runtime(\"fs/join expected a string, but got 1\"))".to_string()),
+    ("(fs/exists? \"/no/such/harp/file\")", "Ok(#f)".to_string()),
+  ];
+  for (code, expected) in &cases {
+    for res in eval_both(code, |_| {}) {
+      assert_eq!(format!("{:?}", res), *expected, "{}", code);
+    }
+  }
+
+  match &eval_both("(fs/read-string \"/no/such/harp/file\")", |_| {})[0] {
+    Err(error::HarpError::Runtime(msg)) => {
+      assert!(msg.starts_with("fs/read-string failed for /no/such/harp/file: "))
     }
-    None => {}
+    res => panic!("expected an error, but got {:?}", res),
   }
 }
+
+#[test]
+fn default_depth_limit_test() {
+  // The default limit must stop scripts before an 8MB main thread stack runs out, in debug builds too
+  let recurse = || {
+    let cases = [
+      ("(defun g (n) (if (eq n 0) 0 (+ 1 (g (- n 1))))) (g 199)", "Ok(199)"),
+      ("(defun g (n) (let [m (- n 1)] (if (eq n 0) 0 (+ 1 (g m))))) (g 199)", "Ok(199)"),
+      ("(defun g (n) (match n 0 0 k (+ 1 (g (- k 1))))) (g 199)", "Ok(199)"),
+    ];
+    assert_cases(&cases);
+    let res = eval_both("(defun g (n) (if (eq n 0) 0 (+ 1 (g (- n 1))))) (g 1000)", |_| {});
+    assert!(res.iter().all(|res| matches!(res, Err(error::HarpError::LimitExceeded(_)))));
+  };
+  std::thread::Builder::new().stack_size(8 << 20).spawn(recurse).unwrap().join().unwrap();
+}
@@ -0,0 +1,312 @@
+/*
+  Native functions are reference counted closures with a name and an arity, so hosts can register functions
+  which capture their own state. Regular natives receive their arguments evaluated, left to right and exactly
+  once. Special forms (if, def, lambda...) receive the unevaluated syntax and decide what to evaluate. The
+  IntoNative impls turn closures over FromValue arguments returning an IntoValue into natives which check and
+  convert their arguments, reporting type errors by position.
+*/
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::value::{EnvHead, Value};
+
+pub type NativeFn = dyn Fn(Vec<Value>, &mut EnvHead) -> EvalResult;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arity {
+  pub min: usize,
+  pub max: Option<usize>,
+}
+
+impl Arity {
+  pub fn exact(n: usize) -> Arity {
+    Arity { min: n, max: Some(n) }
+  }
+
+  pub fn at_least(n: usize) -> Arity {
+    Arity { min: n, max: None }
+  }
+
+  pub fn range(min: usize, max: usize) -> Arity {
+    Arity { min, max: Some(max) }
+  }
+
+  pub fn any() -> Arity {
+    Arity::at_least(0)
+  }
+
+  pub fn accepts(&self, n: usize) -> bool {
+    n >= self.min && self.max.is_none_or(|max| n <= max)
+  }
+
+  pub fn check(&self, name: &str, n: usize) -> Result<(), HarpError> {
+    if self.accepts(n) {
+      return Ok(());
+    }
+    let expected = match self.max {
+      Some(max) if max == self.min => format!("{}", max),
+      Some(max) => format!("{} to {}", self.min, max),
+      None => format!("at least {}", self.min),
+    };
+    Err(HarpError::Runtime(format!(
+      "{} expected {} arguments, but got {}",
+      name, expected, n
+    )))
+  }
+}
+
+#[derive(Clone)]
+pub struct Native {
+  pub name: String,
+  pub arity: Arity,
+  pub special: bool,
+  func: Rc<NativeFn>,
+}
+
+impl fmt::Debug for Native {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Native({})", self.name)
+  }
+}
+
+impl Native {
+  pub fn new<F>(name: &str, arity: Arity, func: F) -> Native
+  where
+    F: Fn(Vec<Value>, &mut EnvHead) -> EvalResult + 'static,
+  {
+    Native {
+      name: name.to_string(),
+      arity,
+      special: false,
+      func: Rc::new(func),
+    }
+  }
+
+  pub fn special<F>(name: &str, arity: Arity, func: F) -> Native
+  where
+    F: Fn(Vec<Value>, &mut EnvHead) -> EvalResult + 'static,
+  {
+    Native {
+      special: true,
+      ..Native::new(name, arity, func)
+    }
+  }
+
+  pub fn call(&self, args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+    self.arity.check(&self.name, args.len())?;
+    (self.func)(args, env)
+  }
+
+  // Two natives are the same function only if they share the closure
+  pub fn ptr_eq(&self, other: &Native) -> bool {
+    Rc::ptr_eq(&self.func, &other.func)
+  }
+
+  // Address of the closure, orders and hashes natives by identity
+  pub fn id(&self) -> usize {
+    Rc::as_ptr(&self.func) as *const () as usize
+  }
+}
+
+pub trait FromValue: Sized {
+  fn from_value(value: Value) -> Result<Self, HarpError>;
+}
+
+pub trait IntoValue {
+  fn into_value(self) -> EvalResult;
+}
+
+fn expected<T>(what: &str, value: &Value) -> Result<T, HarpError> {
+  Err(HarpError::Runtime(format!("expected {}, but got {}", what, value)))
+}
+
+impl FromValue for Value {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    Ok(value)
+  }
+}
+
+impl FromValue for f64 {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::Number(n) => Ok(n),
+      v => expected("a number", &v),
+    }
+  }
+}
+
+impl FromValue for i64 {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::Number(n) if n.fract() == 0.0 => Ok(n as i64),
+      v => expected("an integer", &v),
+    }
+  }
+}
+
+impl FromValue for usize {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::Number(n) if n.fract() == 0.0 && n >= 0.0 => Ok(n as usize),
+      v => expected("a non negative integer", &v),
+    }
+  }
+}
+
+impl FromValue for bool {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::Bool(b) => Ok(b),
+      v => expected("a boolean", &v),
+    }
+  }
+}
+
+impl FromValue for String {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::String(s) => Ok(s),
+      v => expected("a string", &v),
+    }
+  }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::List(xs) => xs.iter().cloned().map(T::from_value).collect(),
+      Value::Vector(xs) => xs.into_iter().map(T::from_value).collect(),
+      Value::Unit => Ok(Vec::new()),
+      v => expected("a list", &v),
+    }
+  }
+}
+
+// Unit stands in for a missing value
+impl<T: FromValue> FromValue for Option<T> {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::Unit => Ok(None),
+      v => T::from_value(v).map(Some),
+    }
+  }
+}
+
+impl IntoValue for Value {
+  fn into_value(self) -> EvalResult {
+    Ok(self)
+  }
+}
+
+impl IntoValue for f64 {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::Number(self))
+  }
+}
+
+impl IntoValue for i64 {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::Number(self as f64))
+  }
+}
+
+impl IntoValue for usize {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::Number(self as f64))
+  }
+}
+
+impl IntoValue for bool {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::Bool(self))
+  }
+}
+
+impl IntoValue for String {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::String(self))
+  }
+}
+
+impl IntoValue for &str {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::String(self.to_string()))
+  }
+}
+
+impl IntoValue for () {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::Unit)
+  }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+  fn into_value(self) -> EvalResult {
+    let xs: Result<Vec<Value>, HarpError> = self.into_iter().map(T::into_value).collect();
+    Ok(Value::list(xs?))
+  }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+  fn into_value(self) -> EvalResult {
+    match self {
+      Some(v) => v.into_value(),
+      None => Ok(Value::Unit),
+    }
+  }
+}
+
+impl<T: IntoValue> IntoValue for Result<T, HarpError> {
+  fn into_value(self) -> EvalResult {
+    self.and_then(T::into_value)
+  }
+}
+
+// Converts the argument at the given position
+pub fn convert_arg<T: FromValue>(name: &str, index: usize, arg: Value) -> Result<T, HarpError> {
+  T::from_value(arg).map_err(|err| match err {
+    HarpError::Runtime(msg) => HarpError::Runtime(format!("{} argument {} {}", name, index + 1, msg)),
+    err => err,
+  })
+}
+
+pub trait IntoNative<Args> {
+  fn into_native(self, name: &str) -> Native;
+}
+
+macro_rules! impl_into_native {
+  ($($arg:ident),*) => {
+    impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+    where
+      F: Fn($($arg),*) -> R + 'static,
+      R: IntoValue,
+      $($arg: FromValue,)*
+    {
+      #[allow(non_snake_case, unused_mut, unused_variables)]
+      fn into_native(self, name: &str) -> Native {
+        let count = 0 $(+ { let $arg = 1; $arg })*;
+        let fname = name.to_string();
+        Native::new(name, Arity::exact(count), move |args, _env| {
+          let mut args = args.into_iter().enumerate();
+          $(
+            let $arg: $arg = match args.next() {
+              Some((i, arg)) => convert_arg(&fname, i, arg)?,
+              None => unreachable!(),
+            };
+          )*
+          self($($arg),*).into_value()
+        })
+      }
+    }
+  };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+impl_into_native!(A, B, C, D, E);
+impl_into_native!(A, B, C, D, E, G);
@@ -1,15 +1,32 @@
+use crate::evaluator::script::JumpTable;
 use crate::evaluator::value::Value;
 
 use std::fmt;
-use std::fmt::*;
 
 #[derive(Clone)]
 pub enum Opcode {
     Push(Value),
     Pop,
     Const(usize),
+    Load(usize),
+    Define(usize),
+    Set(usize),
     Call(usize),
+    // Calls a special form with its arguments unevaluated
+    CallSpecial(usize),
     Jump(usize),
+    JumpIfFalse(usize),
+    // Jumps on the value on top of the stack, leaving it there
+    JumpTable(Box<JumpTable>),
+    // Collects the values on top of the stack, maps take keys and values alternately
+    Vector(usize),
+    Map(usize),
+    // Installs an error handler at the address until the matching EndTry
+    Try(usize),
+    EndTry,
+    // Runs the code up to the matching PopScope in a scope of its own, as the clauses of match do
+    PushScope,
+    PopScope,
     // Label(usize), // Does this really need to be an opcode?
 }
 
@@ -19,8 +36,20 @@ impl fmt::Display for Opcode {
             Opcode::Push(value) => write!(f, "Push({})", value),
             Opcode::Pop => write!(f, "Pop"),
             Opcode::Const(index) => write!(f, "Const({})", index),
+            Opcode::Load(index) => write!(f, "Load(#const: {})", index),
+            Opcode::Define(index) => write!(f, "Define(#const: {})", index),
+            Opcode::Set(index) => write!(f, "Set(#const: {})", index),
             Opcode::Call(args) => write!(f, "Call(#args: {})", args),
+            Opcode::CallSpecial(args) => write!(f, "CallSpecial(#args: {})", args),
             Opcode::Jump(new_pc) => write!(f, "Jump(#addr: {})", new_pc),
+            Opcode::JumpIfFalse(new_pc) => write!(f, "JumpIfFalse(#addr: {})", new_pc),
+            Opcode::JumpTable(table) => write!(f, "JumpTable(#cases: {}, #default: {})", table.len(), table.default),
+            Opcode::Vector(len) => write!(f, "Vector(#len: {})", len),
+            Opcode::Map(len) => write!(f, "Map(#len: {})", len),
+            Opcode::Try(handler) => write!(f, "Try(#addr: {})", handler),
+            Opcode::EndTry => write!(f, "EndTry"),
+            Opcode::PushScope => write!(f, "PushScope"),
+            Opcode::PopScope => write!(f, "PopScope"),
         }
     }
 }
@@ -0,0 +1,186 @@
+/*
+  Parameter lists of defun and lambda: required names or destructuring patterns, then &optional parameters with
+  default expressions, a &rest parameter collecting the remaining arguments and &key parameters passed as :name
+  value pairs.
+
+    (defun greet (name &optional (greeting "Hello") &key (times 1)) ...)
+    (greet "Bob" "Hi" :times 2)
+
+  Defaults are expressions evaluated when the function is called, in the scope of the earlier parameters.
+*/
+
+use std::fmt;
+
+use crate::evaluator::error::HarpError;
+use crate::evaluator::native::Arity;
+use crate::evaluator::pattern::Pattern;
+use crate::evaluator::quick_eval::qeval_value;
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+use crate::harp_err;
+
+#[derive(Clone, Default)]
+pub struct Params {
+  pub required: Vec<Pattern>,
+  pub optional: Vec<(String, Value)>,
+  pub rest: Option<String>,
+  pub keys: Vec<(String, Value)>,
+}
+
+#[derive(PartialEq)]
+enum Section {
+  Required,
+  Optional,
+  Rest,
+  Key,
+}
+
+// A name, or a (name default) pair where defaults are allowed
+fn param_with_default(value: &Value, form: &str) -> Result<(String, Value), HarpError> {
+  match value {
+    Value::Atom(name) => Ok((name.clone(), Value::Unit)),
+    Value::List(xs) => match &xs.to_vec()[..] {
+      [Value::Atom(name), default] => Ok((name.clone(), default.clone())),
+      _ => harp_err!("{} expected (name default), but got {}", form, value),
+    },
+    v => harp_err!("{} expects a list of parameters, got {}", form, v),
+  }
+}
+
+impl Params {
+  pub fn parse(values: &[Value], form: &str) -> Result<Params, HarpError> {
+    let mut params = Params::default();
+    let mut section = Section::Required;
+
+    for value in values {
+      match value {
+        Value::Atom(marker) if marker == "&optional" => {
+          if section != Section::Required {
+            return harp_err!("{} has &optional in the wrong place", form);
+          }
+          section = Section::Optional;
+        }
+        Value::Atom(marker) if marker == "&rest" => {
+          if section == Section::Rest || section == Section::Key || params.rest.is_some() {
+            return harp_err!("{} has &rest in the wrong place", form);
+          }
+          section = Section::Rest;
+        }
+        Value::Atom(marker) if marker == "&key" => {
+          if section == Section::Key || (section == Section::Rest && params.rest.is_none()) {
+            return harp_err!("{} has &key in the wrong place", form);
+          }
+          section = Section::Key;
+        }
+        _ => match section {
+          Section::Required => match value {
+            Value::Atom(_) | Value::Vector(_) | Value::Map(_) => params.required.push(Pattern::parse(value)?),
+            v => return harp_err!("{} expects a list of parameters, got {}", form, v),
+          },
+          Section::Optional => params.optional.push(param_with_default(value, form)?),
+          Section::Rest => match value {
+            Value::Atom(name) if params.rest.is_none() => params.rest = Some(name.clone()),
+            v => return harp_err!("{} expected one name after &rest, but got {}", form, v),
+          },
+          Section::Key => params.keys.push(param_with_default(value, form)?),
+        },
+      }
+    }
+
+    if section == Section::Rest && params.rest.is_none() {
+      return harp_err!("{} expected a name after &rest", form);
+    }
+    Ok(params)
+  }
+
+  pub fn arity(&self) -> Arity {
+    let min = self.required.len();
+    if self.rest.is_some() || !self.keys.is_empty() {
+      Arity::at_least(min)
+    } else {
+      Arity::range(min, min + self.optional.len())
+    }
+  }
+
+  // Binds evaluated arguments in the function's scope
+  pub fn bind(&self, name: &str, args: Vec<Value>, scope: &mut EnvHead) -> Result<(), HarpError> {
+    self.arity().check(name, args.len())?;
+    let mut args = args.into_iter();
+
+    for pattern in &self.required {
+      pattern.bind(args.next().unwrap_or(Value::Unit), scope)?;
+    }
+    for (param, default) in &self.optional {
+      let value = match args.next() {
+        Some(value) => value,
+        None => qeval_value(default.clone(), scope)?,
+      };
+      bind_value(param, value, scope)?;
+    }
+
+    let remaining: Vec<Value> = args.collect();
+    if !self.keys.is_empty() {
+      self.bind_keys(name, &remaining, scope)?;
+    }
+    if let Some(rest) = &self.rest {
+      bind_value(rest, Value::list(remaining), scope)?;
+    }
+    Ok(())
+  }
+
+  fn bind_keys(&self, name: &str, args: &[Value], scope: &mut EnvHead) -> Result<(), HarpError> {
+    if !args.len().is_multiple_of(2) {
+      return harp_err!("{} expected keyword arguments in pairs", name);
+    }
+
+    let mut given = Vec::new();
+    for pair in args.chunks(2) {
+      let key = match &pair[0] {
+        Value::Atom(key) if is_keyword(key) => &key[1..],
+        v => return harp_err!("{} expected a keyword, but got {}", name, v),
+      };
+      if !self.keys.iter().any(|(param, _)| param == key) {
+        return harp_err!("{} got an unknown keyword argument :{}", name, key);
+      }
+      given.push((key.to_string(), pair[1].clone()));
+    }
+
+    for (param, default) in &self.keys {
+      let value = match given.iter().rev().find(|(key, _)| key == param) {
+        Some((_, value)) => value.clone(),
+        None => qeval_value(default.clone(), scope)?,
+      };
+      bind_value(param, value, scope)?;
+    }
+    Ok(())
+  }
+
+  // Every name in order, with the section markers
+  pub fn names(&self) -> Vec<String> {
+    let mut names: Vec<String> = self.required.iter().map(|pattern| pattern.to_string()).collect();
+    if !self.optional.is_empty() {
+      names.push("&optional".to_string());
+      names.extend(self.optional.iter().map(|(name, _)| name.clone()));
+    }
+    if let Some(rest) = &self.rest {
+      names.push("&rest".to_string());
+      names.push(rest.clone());
+    }
+    if !self.keys.is_empty() {
+      names.push("&key".to_string());
+      names.extend(self.keys.iter().map(|(name, _)| name.clone()));
+    }
+    names
+  }
+}
+
+fn bind_value(name: &str, value: Value, scope: &mut EnvHead) -> Result<(), HarpError> {
+  scope.budget().alloc(&value)?;
+  scope.set(name.to_string(), value);
+  Ok(())
+}
+
+impl fmt::Debug for Params {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:?}", self.names())
+  }
+}
@@ -0,0 +1,165 @@
+/*
+  Destructuring patterns, used wherever a name is bound: let, function parameters and doseq.
+
+    [a b & rest]      binds the elements of a list or vector, rest gets the remaining ones as a list
+    {:keys [x y]}     binds the :x and :y entries of a map, or fields of a record or variant
+    {name :name}      binds the entry for a key to a pattern
+    [a b :as all]     also binds the whole value, works for maps too
+
+  Missing elements and entries bind to ().
+*/
+
+use std::fmt;
+
+use crate::evaluator::error::HarpError;
+use crate::evaluator::value::{lookup, EnvHead, Value};
+use crate::harp_err;
+
+#[derive(Clone)]
+pub enum Pattern {
+  Name(String),
+  Seq {
+    items: Vec<Pattern>,
+    rest: Option<Box<Pattern>>,
+    all: Option<String>,
+  },
+  Map {
+    entries: Vec<(Pattern, Value)>,
+    all: Option<String>,
+  },
+}
+
+fn name_after(marker: &str, value: Option<&Value>) -> Result<String, HarpError> {
+  match value {
+    Some(Value::Atom(name)) => Ok(name.clone()),
+    Some(v) => harp_err!("Expected a name after {}, but got {}", marker, v),
+    None => harp_err!("Expected a name after {}", marker),
+  }
+}
+
+impl Pattern {
+  pub fn parse(value: &Value) -> Result<Pattern, HarpError> {
+    match value {
+      Value::Atom(name) => Ok(Pattern::Name(name.clone())),
+      Value::Vector(xs) => {
+        let mut items = Vec::new();
+        let mut rest = None;
+        let mut all = None;
+        let mut xs = xs.iter();
+        while let Some(x) = xs.next() {
+          match x {
+            Value::Atom(marker) if marker == "&" => match xs.next() {
+              Some(pattern) if rest.is_none() => rest = Some(Box::new(Pattern::parse(pattern)?)),
+              _ => return harp_err!("Expected one pattern after & in {}", value),
+            },
+            Value::Atom(marker) if marker == ":as" => all = Some(name_after(":as", xs.next())?),
+            x if rest.is_some() => return harp_err!("Unexpected {} after the rest pattern in {}", x, value),
+            x => items.push(Pattern::parse(x)?),
+          }
+        }
+        Ok(Pattern::Seq { items, rest, all })
+      }
+      Value::Map(pairs) => {
+        let mut entries = Vec::new();
+        let mut all = None;
+        for (key, lookup) in pairs {
+          match (key, lookup) {
+            (Value::Atom(marker), Value::Vector(names)) if marker == ":keys" => {
+              for name in names {
+                match name {
+                  Value::Atom(name) => {
+                    entries.push((Pattern::Name(name.clone()), Value::Atom(format!(":{}", name))))
+                  }
+                  v => return harp_err!(":keys expected names, but got {}", v),
+                }
+              }
+            }
+            (Value::Atom(marker), _) if marker == ":as" => all = Some(name_after(":as", Some(lookup))?),
+            (pattern, key) => entries.push((Pattern::parse(pattern)?, key.clone())),
+          }
+        }
+        Ok(Pattern::Map { entries, all })
+      }
+      v => harp_err!("Expected a name or a pattern, but got {}", v),
+    }
+  }
+
+  pub fn bind(&self, value: Value, scope: &mut EnvHead) -> Result<(), HarpError> {
+    match self {
+      Pattern::Name(name) => {
+        scope.budget().alloc(&value)?;
+        scope.set(name.clone(), value);
+      }
+      Pattern::Seq { items, rest, all } => {
+        match &value {
+          Value::List(xs) => bind_items(items, xs.iter(), scope)?,
+          Value::Vector(xs) => bind_items(items, xs.iter(), scope)?,
+          Value::Unit => bind_items(items, [].iter(), scope)?,
+          v => return harp_err!("Cannot destructure {} with {}", v, self),
+        }
+        if let Some(rest) = rest {
+          // A list's rest shares its cells
+          let tail = match &value {
+            Value::List(xs) => Value::List(xs.skip(items.len())),
+            Value::Vector(xs) => Value::list(xs.iter().skip(items.len()).cloned().collect()),
+            _ => Value::list(Vec::new()),
+          };
+          rest.bind(tail, scope)?;
+        }
+        if let Some(all) = all {
+          Pattern::Name(all.clone()).bind(value, scope)?;
+        }
+      }
+      Pattern::Map { entries, all } => {
+        if !matches!(value, Value::Map(_) | Value::Record(_) | Value::Variant(_) | Value::Unit) {
+          return harp_err!("Cannot destructure {} with {}", value, self);
+        }
+        for (pattern, key) in entries {
+          let entry = lookup(&value, key).cloned().unwrap_or(Value::Unit);
+          pattern.bind(entry, scope)?;
+        }
+        if let Some(all) = all {
+          Pattern::Name(all.clone()).bind(value, scope)?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+// Missing elements bind to ()
+fn bind_items<'a>(
+  items: &[Pattern],
+  mut xs: impl Iterator<Item = &'a Value>,
+  scope: &mut EnvHead,
+) -> Result<(), HarpError> {
+  for item in items {
+    item.bind(xs.next().cloned().unwrap_or(Value::Unit), scope)?;
+  }
+  Ok(())
+}
+
+impl fmt::Display for Pattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Pattern::Name(name) => write!(f, "{}", name),
+      Pattern::Seq { items, rest, all } => {
+        let mut parts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+        if let Some(rest) = rest {
+          parts.push(format!("& {}", rest));
+        }
+        if let Some(all) = all {
+          parts.push(format!(":as {}", all));
+        }
+        write!(f, "[{}]", parts.join(" "))
+      }
+      Pattern::Map { entries, all } => {
+        let mut parts: Vec<String> = entries.iter().map(|(p, key)| format!("{} {}", p, key)).collect();
+        if let Some(all) = all {
+          parts.push(format!(":as {}", all));
+        }
+        write!(f, "{{{}}}", parts.join(" "))
+      }
+    }
+  }
+}
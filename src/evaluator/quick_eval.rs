@@ -3,85 +3,197 @@
 	retire this module in favor of the vm and translator
 */
 
-use crate::evaluator::value::{EnvHead, Value};
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::native::Native;
+use crate::evaluator::params::Params;
+use crate::evaluator::collections::{List, Map, Vector};
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+use crate::harp_err;
 use crate::reader::ast::{to_value, Node};
 
-pub fn qeval_value(value: Value, env: &mut EnvHead) -> Value {
+fn call_func<'a>(
+	name: &str,
+	params: &Params,
+	progn: Value,
+	args: impl Iterator<Item = &'a Value>,
+	env: &mut EnvHead,
+) -> EvalResult {
+	let mut values = Vec::new();
+	for value in args {
+		values.push(qeval_value(value.clone(), env)?);
+	}
+	apply_func(name, params, progn, values, env)
+}
+
+fn apply_func(name: &str, params: &Params, progn: Value, args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+	let mut scope = env.clone().push();
+	params.bind(name, args, &mut scope)?;
+
+	env.budget().enter()?;
+	let result = qeval_value(progn, &mut scope);
+	env.budget().leave();
+	result
+}
+
+// Special forms evaluate their own arguments, so already evaluated values which are not
+// self-evaluating are quoted before being handed over
+pub fn quote_arg(value: Value) -> Value {
+	match value {
+		Value::Atom(_) | Value::List(_) | Value::Vector(_) | Value::Map(_) | Value::Do(_) => {
+			Value::list(vec![Value::Atom("quote".to_string()), value])
+		}
+		value => value,
+	}
+}
+
+// Calls a function with already evaluated arguments, as a host would
+pub fn qapply(callee: Value, args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+	env.budget().step()?;
+	match callee {
+		Value::Func(name, params, progn) => apply_func(&name, &params, Value::clone(&progn), args, env),
+		Value::NativeFunc(native) => apply_native(&native, args, env),
+		v => harp_err!("Cannot function call on function {}", v),
+	}
+}
+
+// Calls a native with already evaluated arguments
+pub fn apply_native(native: &Native, args: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+	let args = if native.special {
+		args.into_iter().map(quote_arg).collect()
+	} else {
+		args
+	};
+	let result = native.call(args, env)?;
+	env.budget().alloc(&result)?;
+	Ok(result)
+}
+
+// Calls a native from syntax, only regular natives get their arguments evaluated
+pub fn call_native<'a>(
+	native: &Native,
+	args: impl ExactSizeIterator<Item = &'a Value>,
+	env: &mut EnvHead,
+) -> EvalResult {
+	let args = if native.special {
+		args.cloned().collect()
+	} else {
+		let mut values = Vec::with_capacity(args.len());
+		for arg in args {
+			values.push(qeval_value(arg.clone(), env)?);
+		}
+		values
+	};
+	let result = native.call(args, env)?;
+	env.budget().alloc(&result)?;
+	Ok(result)
+}
+
+pub fn qeval_value(value: Value, env: &mut EnvHead) -> EvalResult {
 	match value {
 		Value::Number(_)
 		| Value::String(_)
 		| Value::Bool(_)
 		| Value::NativeFunc(_)
 		| Value::Func(_, _, _)
-		| Value::Unit => value,
-		Value::Atom(name) => match env.get(name.clone()) {
-			Some(value) => value,
-			None => {
-				println!("Undefind Variable {}", name);
-				return Value::Unit;
-			}
-		},
-		Value::Do(xs) => {
-			let mut res: Vec<Value> = Vec::new();
+		| Value::UserData(_)
+		| Value::Record(_)
+		| Value::Variant(_)
+		| Value::Lazy(_)
+		| Value::Regex(_)
+		| Value::Unit => Ok(value),
+		Value::Atom(name) => qeval_atom(name, env),
+		Value::Vector(xs) => qeval_vector(xs, env),
+		Value::Map(entries) => qeval_map(entries, env),
+		Value::Do(xs) => qeval_do(xs, env),
+		Value::List(xs) => qeval_call(&xs, env),
+	}
+}
 
-			for expr in xs {
-				res.push(qeval_value(expr, env));
-			}
+// The cases of qeval_value with locals live in their own functions. Every nested call of a script goes through
+// qeval_value several times, so its frame decides how deep scripts can recurse before the stack runs out
 
-			match res.last() {
-				Some(value) => value.clone(),
-				None => Value::Unit,
-			}
-		}
-		Value::List(xs) => {
-			let first = &xs[0];
-			match qeval_value(first.clone(), env) {
-				Value::Func(_, params, progn) => {
-					let mut scope = env.clone().push();
-					for (value, name) in xs.iter().skip(1).zip(params) {
-						scope.set(name, qeval_value(value.clone(), env));
-					}
-					qeval_value(*progn, &mut scope)
-				}
-				Value::NativeFunc(callable) => {
-					let mut args = Vec::<Value>::new();
-					for n in xs.iter().skip(1) {
-						args.push(n.clone());
-					}
-					callable(args, env)
-				}
-				Value::Atom(name) => match env.get(name.to_string()) {
-					Some(Value::NativeFunc(callable)) => {
-						let mut args = Vec::<Value>::new();
-						for n in xs.iter().skip(1) {
-							args.push(n.clone());
-						}
-						callable(args, env)
-					}
-					Some(Value::Func(_name, params, progn)) => {
-						let mut scope = env.clone().push();
-						for (value, name) in xs.iter().skip(1).zip(params) {
-							scope.set(name, qeval_value(value.clone(), env));
-						}
-						qeval_value(*progn, &mut scope)
-					}
-					Some(v) => panic!("Illegal function call. {} is {}", name, v),
-					None => panic!("Undefined function {}", name),
-				},
-				v => panic!("Cannot function call on function {}", v),
-			}
+fn qeval_atom(name: String, env: &mut EnvHead) -> EvalResult {
+	if is_keyword(&name) {
+		return Ok(Value::Atom(name));
+	}
+	match env.get(name.clone()) {
+		Some(value) => Ok(value),
+		None => {
+			env.write_err(&format!("Undefind Variable {}\n", name));
+			Ok(Value::Unit)
 		}
-		_ => panic!("Cannot evaluate value {}", value),
 	}
 }
 
-pub fn qeval_expr(expr: &Node, env: &mut EnvHead) -> Value {
-	return qeval_value(to_value(expr), env);
+fn qeval_do(xs: Vec<Value>, env: &mut EnvHead) -> EvalResult {
+	let mut res = Value::Unit;
+
+	for expr in xs {
+		res = qeval_value(expr, env)?;
+	}
+
+	Ok(res)
 }
 
-pub fn qeval_progn(progn: &Node, env: &mut EnvHead) -> Value {
-	return match progn {
-		Node::Progn(ns, _) => ns.iter().map(|e| qeval_expr(e, env)).last().unwrap(),
-		_ => qeval_expr(progn, env),
+fn qeval_vector(xs: Vector, env: &mut EnvHead) -> EvalResult {
+	let mut values = Vector::new();
+	for x in xs {
+		values.push_back(qeval_value(x, env)?);
+	}
+	Ok(Value::Vector(values))
+}
+
+fn qeval_map(entries: Map, env: &mut EnvHead) -> EvalResult {
+	let mut map = Map::default();
+	for (key, value) in entries {
+		let key = qeval_value(key, env)?;
+		let value = qeval_value(value, env)?;
+		map.insert(key, value);
+	}
+	Ok(Value::Map(map))
+}
+
+// The arguments stay in the call's list, they are read from it without being copied
+fn qeval_call(xs: &List, env: &mut EnvHead) -> EvalResult {
+	env.budget().step()?;
+
+	let first = match xs.first() {
+		Some(first) => first,
+		None => return Err(HarpError::runtime("Cannot call an empty list")),
 	};
+	let args = xs.rest();
+	match callee(first.clone(), env)? {
+		Value::Func(name, params, progn) => call_func(&name, &params, Value::clone(&progn), args.iter(), env),
+		Value::NativeFunc(native) => call_native(&native, args.iter(), env),
+		v => harp_err!("Cannot function call on function {}", v),
+	}
+}
+
+// The function the head of a call names, an atom the head evaluates to is looked up once more
+fn callee(head: Value, env: &mut EnvHead) -> EvalResult {
+	match qeval_value(head, env)? {
+		Value::Atom(name) => match env.get(name.to_string()) {
+			Some(func @ Value::NativeFunc(_)) | Some(func @ Value::Func(_, _, _)) => Ok(func),
+			Some(v) => harp_err!("Illegal function call. {} is {}", name, v),
+			None => harp_err!("Undefined function {}", name),
+		},
+		func => Ok(func),
+	}
+}
+
+pub fn qeval_expr(expr: &Node, env: &mut EnvHead) -> EvalResult {
+	qeval_value(to_value(expr), env)
+}
+
+pub fn qeval_progn(progn: &Node, env: &mut EnvHead) -> EvalResult {
+	match progn {
+		Node::Progn(ns, _) => {
+			let mut res = Value::Unit;
+			for n in ns {
+				res = qeval_expr(n, env)?;
+			}
+			Ok(res)
+		}
+		_ => qeval_expr(progn, env),
+	}
 }
@@ -0,0 +1,96 @@
+/*
+  Compiled regular expressions, made by (re/compile "go (\w+)") or the literal #r"go (\w+)" which the reader
+  compiles, so a bad pattern is a syntax error. The syntax is the regex crate's.
+
+  A match is returned as the matched text when the pattern has no groups, as a vector of the match followed by
+  its groups when it has groups, and as a map from :name to text when it has named groups. Groups which didn't
+  take part in the match are ().
+*/
+
+use std::fmt;
+
+use regex::{Captures, Regex};
+
+use crate::evaluator::collections::Map;
+use crate::evaluator::error::HarpError;
+use crate::evaluator::value::Value;
+
+#[derive(Clone)]
+pub struct Re {
+  find: Regex,
+  // The same pattern anchored at both ends, for matching a whole string
+  whole: Regex,
+}
+
+impl Re {
+  pub fn new(pattern: &str) -> Result<Re, HarpError> {
+    let compile = |pattern: &str| Regex::new(pattern).map_err(|err| HarpError::Runtime(err.to_string()));
+    Ok(Re {
+      find: compile(pattern)?,
+      whole: compile(&format!("\\A(?:{})\\z", pattern))?,
+    })
+  }
+
+  pub fn as_str(&self) -> &str {
+    self.find.as_str()
+  }
+
+  pub fn regex(&self) -> &Regex {
+    &self.find
+  }
+
+  // The match of the whole string
+  pub fn matches(&self, s: &str) -> Value {
+    match self.whole.captures(s) {
+      Some(caps) => self.to_value(&caps),
+      None => Value::Unit,
+    }
+  }
+
+  pub fn find(&self, s: &str) -> Value {
+    match self.find.captures(s) {
+      Some(caps) => self.to_value(&caps),
+      None => Value::Unit,
+    }
+  }
+
+  pub fn find_all(&self, s: &str) -> Vec<Value> {
+    self.find.captures_iter(s).map(|caps| self.to_value(&caps)).collect()
+  }
+
+  fn to_value(&self, caps: &Captures) -> Value {
+    let text = |m: Option<regex::Match>| match m {
+      Some(m) => Value::String(m.as_str().to_string()),
+      None => Value::Unit,
+    };
+    if self.find.capture_names().flatten().next().is_some() {
+      let mut map = Map::default();
+      for name in self.find.capture_names().flatten() {
+        map.insert(Value::Atom(format!(":{}", name)), text(caps.name(name)));
+      }
+      Value::Map(map)
+    } else if caps.len() > 1 {
+      Value::vector(caps.iter().map(text).collect())
+    } else {
+      text(caps.get(0))
+    }
+  }
+}
+
+impl PartialEq for Re {
+  fn eq(&self, other: &Re) -> bool {
+    self.as_str() == other.as_str()
+  }
+}
+
+impl fmt::Display for Re {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "#r\"{}\"", self.as_str())
+  }
+}
+
+impl fmt::Debug for Re {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self)
+  }
+}
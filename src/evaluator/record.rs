@@ -0,0 +1,128 @@
+/*
+  Records are values of a named type with a fixed set of fields, made by defrecord:
+
+    (defrecord Room [name exits])
+
+  defines make-room, room?, room-name and room-exits. Records print as #Room{:name hall :exits []},
+  are equal when they have the same type and equal fields, and (assoc room :name "cellar") returns an
+  updated copy. Every defrecord makes a new type, records of an earlier definition are not equal to it.
+*/
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::evaluator::error::HarpError;
+use crate::evaluator::native::{Arity, Native};
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+use crate::harp_err;
+
+pub struct RecordType {
+  pub name: String,
+  pub fields: Vec<String>,
+}
+
+impl RecordType {
+  pub fn field_index(&self, field: &str) -> Option<usize> {
+    self.fields.iter().position(|f| f == field)
+  }
+
+  // Prefix of the generated functions, Room makes room-name
+  pub fn prefix(&self) -> String {
+    self.name.to_lowercase()
+  }
+}
+
+#[derive(Clone)]
+pub struct Record {
+  pub ty: Rc<RecordType>,
+  pub fields: Vec<Value>,
+}
+
+// Field keys are keywords, :name for the field name
+fn field_name(key: &Value) -> Option<&str> {
+  match key {
+    Value::Atom(key) if is_keyword(key) => Some(&key[1..]),
+    _ => None,
+  }
+}
+
+impl Record {
+  pub fn get(&self, key: &Value) -> Option<&Value> {
+    let index = self.ty.field_index(field_name(key)?)?;
+    self.fields.get(index)
+  }
+
+  // A copy with the field set, unknown fields are an error
+  pub fn with(&self, key: &Value, value: Value) -> Result<Record, HarpError> {
+    match field_name(key).and_then(|field| self.ty.field_index(field)) {
+      Some(index) => {
+        let mut record = self.clone();
+        record.fields[index] = value;
+        Ok(record)
+      }
+      None => harp_err!("{} has no field {}", self.ty.name, key),
+    }
+  }
+
+  pub fn is(&self, ty: &Rc<RecordType>) -> bool {
+    Rc::ptr_eq(&self.ty, ty)
+  }
+}
+
+impl PartialEq for Record {
+  fn eq(&self, other: &Record) -> bool {
+    self.is(&other.ty) && self.fields == other.fields
+  }
+}
+
+impl fmt::Display for Record {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "#{}{{", self.ty.name)?;
+    for (i, (field, value)) in self.ty.fields.iter().zip(&self.fields).enumerate() {
+      if i > 0 {
+        write!(f, " ")?;
+      }
+      write!(f, ":{} {}", field, value)?;
+    }
+    write!(f, "}}")
+  }
+}
+
+// Binds the constructor, predicate and accessors of a new record type
+pub fn define_record(name: &str, fields: Vec<String>, env: &mut EnvHead) -> Rc<RecordType> {
+  let ty = Rc::new(RecordType {
+    name: name.to_string(),
+    fields,
+  });
+  let prefix = ty.prefix();
+
+  let make = format!("make-{}", prefix);
+  let owner = ty.clone();
+  let constructor = Native::new(&make, Arity::exact(ty.fields.len()), move |args, _env| {
+    Ok(Value::Record(Record {
+      ty: owner.clone(),
+      fields: args,
+    }))
+  });
+  env.set(make, Value::NativeFunc(constructor));
+
+  let is = format!("{}?", prefix);
+  let owner = ty.clone();
+  let predicate = Native::new(&is, Arity::exact(1), move |args, _env| {
+    Ok(Value::Bool(matches!(&args[0], Value::Record(r) if r.is(&owner))))
+  });
+  env.set(is, Value::NativeFunc(predicate));
+
+  for (index, field) in ty.fields.iter().enumerate() {
+    let accessor = format!("{}-{}", prefix, field);
+    let owner = ty.clone();
+    let name = accessor.clone();
+    let native = Native::new(&accessor, Arity::exact(1), move |args, _env| match &args[0] {
+      Value::Record(r) if r.is(&owner) => Ok(r.fields[index].clone()),
+      v => harp_err!("{} expected a {}, but got {}", name, owner.name, v),
+    });
+    env.set(accessor, Value::NativeFunc(native));
+  }
+
+  ty
+}
@@ -1,14 +1,88 @@
 use crate::evaluator::opcodes::Opcode;
-use crate::evaluator::quick_eval::{qeval_expr, qeval_value};
 use crate::evaluator::value::Value;
 use std::collections::HashMap;
 
-use std::io;
-use std::io::*;
+// Hashable stand-in for the constant kinds the translator pools
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ConstKey {
+  Bool(bool),
+  Number(u64),
+  String(String),
+  Atom(String),
+}
+
+impl ConstKey {
+  fn of(v: &Value) -> Option<ConstKey> {
+    match v {
+      // Normalize so 0 and -0 share a slot, as they compare equal
+      Value::Bool(b) => Some(ConstKey::Bool(*b)),
+      Value::Number(n) => Some(ConstKey::Number((n + 0.0).to_bits())),
+      Value::String(s) => Some(ConstKey::String(s.clone())),
+      Value::Atom(a) => Some(ConstKey::Atom(a.clone())),
+      _ => None,
+    }
+  }
+}
+
+// Dispatch on literal values, with the address to go to when none is equal
+#[derive(Clone, Default)]
+pub struct JumpTable {
+  cases: HashMap<ConstKey, usize>,
+  pub default: usize,
+}
+
+impl JumpTable {
+  pub fn new() -> JumpTable {
+    JumpTable::default()
+  }
+
+  // Only the first case for a value counts. False for values which can't be table keys
+  pub fn insert(&mut self, value: &Value, addr: usize) -> bool {
+    match ConstKey::of(value) {
+      Some(key) => {
+        self.cases.entry(key).or_insert(addr);
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub fn can_hold(value: &Value) -> bool {
+    ConstKey::of(value).is_some()
+  }
+
+  pub fn target(&self, value: &Value) -> usize {
+    match ConstKey::of(value).and_then(|key| self.cases.get(&key)) {
+      Some(addr) => *addr,
+      None => self.default,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.cases.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.cases.is_empty()
+  }
+
+  pub fn targets(&self) -> impl Iterator<Item = usize> + '_ {
+    self.cases.values().copied().chain(std::iter::once(self.default))
+  }
+
+  pub fn map_targets<F: Fn(usize) -> usize>(&self, f: F) -> JumpTable {
+    JumpTable {
+      cases: self.cases.iter().map(|(key, addr)| (key.clone(), f(*addr))).collect(),
+      default: f(self.default),
+    }
+  }
+}
 
+#[derive(Clone, Default)]
 pub struct Script {
   pub constants: Vec<Value>,
   pub instructions: Vec<Opcode>,
+  pool: HashMap<ConstKey, usize>,
 }
 
 impl Script {
@@ -16,26 +90,40 @@ impl Script {
     Script {
       constants: Vec::new(),
       instructions: Vec::new(),
+      pool: HashMap::new(),
     }
   }
 
-  pub fn clone(&self) -> Script {
-    Script {
-      constants: self.constants.clone(),
-      instructions: self.instructions.clone(),
+  pub fn contains_const(&self, v: &Value) -> Option<usize> {
+    match ConstKey::of(v) {
+      Some(key) => self.pool.get(&key).copied(),
+      None => self.constants.iter().position(|b| b == v),
     }
   }
 
-  pub fn contains_const(&mut self, v: &Value) -> Option<usize> {
-    self.constants.iter().position(|b| b == v)
-  }
-
   pub fn new_const(&mut self, v: &Value) -> usize {
     self.constants.push(v.clone());
-    self.constants.len() - 1
+    let index = self.constants.len() - 1;
+    if let Some(key) = ConstKey::of(v) {
+      self.pool.entry(key).or_insert(index);
+    }
+    index
   }
 
   pub fn new_inst(&mut self, op: Opcode) {
     self.instructions.push(op);
   }
+
+  // Address of the next instruction to be emitted
+  pub fn next_addr(&self) -> usize {
+    self.instructions.len()
+  }
+
+  // Rewrites the target of a previously emitted jump
+  pub fn patch_jump(&mut self, at: usize, addr: usize) {
+    match &mut self.instructions[at] {
+      Opcode::Jump(target) | Opcode::JumpIfFalse(target) | Opcode::Try(target) => *target = addr,
+      op => panic!("Cannot patch non-jump instruction {}", op),
+    }
+  }
 }
@@ -0,0 +1,193 @@
+/*
+  Userdata hands host objects to scripts. The object itself is opaque to Harp, what a script can do with it is
+  described by its UserType: a name, how it prints, how it compares and a table of methods. Registering a type
+  binds each method as `type.method`, so `(player.move p 3)` calls the move method with p as its receiver.
+*/
+
+use std::any::Any;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::evaluator::error::{EvalResult, HarpError};
+use crate::evaluator::native::{FromValue, IntoNative, IntoValue, Native};
+use crate::evaluator::value::{EnvHead, Value};
+
+type DisplayFn = dyn Fn(&dyn Any) -> String;
+type EqFn = dyn Fn(&dyn Any, &dyn Any) -> bool;
+
+pub struct UserType {
+  pub name: String,
+  methods: HashMap<String, Native>,
+  display: Option<Rc<DisplayFn>>,
+  equals: Option<Rc<EqFn>>,
+}
+
+impl UserType {
+  pub fn new(name: &str) -> UserType {
+    UserType {
+      name: name.to_string(),
+      methods: HashMap::new(),
+      display: None,
+      equals: None,
+    }
+  }
+
+  // How values of this type print, instead of #<name>
+  pub fn with_display<T: 'static, F: Fn(&T) -> String + 'static>(mut self, display: F) -> UserType {
+    self.display = Some(Rc::new(move |any: &dyn Any| match any.downcast_ref::<T>() {
+      Some(value) => display(value),
+      None => String::new(),
+    }));
+    self
+  }
+
+  // Compare values of this type with PartialEq, instead of by identity
+  pub fn with_eq<T: PartialEq + 'static>(mut self) -> UserType {
+    self.equals = Some(Rc::new(|a: &dyn Any, b: &dyn Any| {
+      match (a.downcast_ref::<T>(), b.downcast_ref::<T>()) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+      }
+    }));
+    self
+  }
+
+  // The receiver is the first argument, taken as UserData
+  pub fn with_method<Args, F: IntoNative<Args>>(mut self, name: &str, method: F) -> UserType {
+    let full_name = format!("{}.{}", self.name, name);
+    self.methods.insert(name.to_string(), method.into_native(&full_name));
+    self
+  }
+
+  pub fn method(&self, name: &str) -> Option<&Native> {
+    self.methods.get(name)
+  }
+
+  pub fn methods(&self) -> impl Iterator<Item = (&String, &Native)> {
+    self.methods.iter()
+  }
+}
+
+// Binds the methods of a type as globals which check their receiver before calling the method
+pub fn register_type(env: &mut EnvHead, ty: UserType) -> Rc<UserType> {
+  let ty = Rc::new(ty);
+  for (name, method) in ty.methods() {
+    let full_name = format!("{}.{}", ty.name, name);
+    let method = method.clone();
+    let owner = ty.clone();
+    let checked = Native::new(&full_name, method.arity, move |args, env| {
+      match args.first() {
+        Some(Value::UserData(data)) if Rc::ptr_eq(&data.ty, &owner) => {}
+        Some(v) => {
+          return Err(HarpError::Runtime(format!(
+            "{} expected a {} receiver, but got {}",
+            method.name, owner.name, v
+          )))
+        }
+        None => {}
+      }
+      method.call(args, env)
+    });
+    env.set(full_name, Value::NativeFunc(checked));
+  }
+  ty
+}
+
+#[derive(Clone)]
+pub struct UserData {
+  ty: Rc<UserType>,
+  data: Rc<RefCell<dyn Any>>,
+}
+
+impl UserData {
+  pub fn new<T: 'static>(ty: &Rc<UserType>, value: T) -> UserData {
+    UserData {
+      ty: ty.clone(),
+      data: Rc::new(RefCell::new(value)),
+    }
+  }
+
+  pub fn type_name(&self) -> &str {
+    &self.ty.name
+  }
+
+  pub fn user_type(&self) -> &Rc<UserType> {
+    &self.ty
+  }
+
+  pub fn is<T: 'static>(&self) -> bool {
+    self.data.borrow().is::<T>()
+  }
+
+  pub fn borrow<T: 'static>(&self) -> Result<Ref<'_, T>, HarpError> {
+    let cell = self.data.try_borrow().map_err(|_| self.busy())?;
+    Ref::filter_map(cell, |any| any.downcast_ref::<T>()).map_err(|_| self.mismatch::<T>())
+  }
+
+  pub fn borrow_mut<T: 'static>(&self) -> Result<RefMut<'_, T>, HarpError> {
+    let cell = self.data.try_borrow_mut().map_err(|_| self.busy())?;
+    RefMut::filter_map(cell, |any| any.downcast_mut::<T>()).map_err(|_| self.mismatch::<T>())
+  }
+
+  // Identity, two handles to the same object
+  pub fn ptr_eq(&self, other: &UserData) -> bool {
+    Rc::ptr_eq(&self.data, &other.data)
+  }
+
+  pub fn id(&self) -> usize {
+    Rc::as_ptr(&self.data) as *const () as usize
+  }
+
+  fn busy(&self) -> HarpError {
+    HarpError::Runtime(format!("{} is already borrowed", self.ty.name))
+  }
+
+  fn mismatch<T>(&self) -> HarpError {
+    HarpError::Runtime(format!(
+      "Expected {}, but got a {}",
+      std::any::type_name::<T>(),
+      self.ty.name
+    ))
+  }
+}
+
+impl PartialEq for UserData {
+  fn eq(&self, other: &UserData) -> bool {
+    if self.ptr_eq(other) {
+      return true;
+    }
+    if !Rc::ptr_eq(&self.ty, &other.ty) {
+      return false;
+    }
+    match (&self.ty.equals, self.data.try_borrow(), other.data.try_borrow()) {
+      (Some(equals), Ok(a), Ok(b)) => equals(&*a, &*b),
+      _ => false,
+    }
+  }
+}
+
+impl fmt::Display for UserData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match (&self.ty.display, self.data.try_borrow()) {
+      (Some(display), Ok(data)) => write!(f, "{}", display(&*data)),
+      _ => write!(f, "#<{}>", self.ty.name),
+    }
+  }
+}
+
+impl FromValue for UserData {
+  fn from_value(value: Value) -> Result<Self, HarpError> {
+    match value {
+      Value::UserData(data) => Ok(data),
+      v => Err(HarpError::Runtime(format!("expected userdata, but got {}", v))),
+    }
+  }
+}
+
+impl IntoValue for UserData {
+  fn into_value(self) -> EvalResult {
+    Ok(Value::UserData(self))
+  }
+}
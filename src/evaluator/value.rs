@@ -1,5 +1,22 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::io::{stderr, stdout, Write};
+use std::rc::Rc;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::evaluator::adt::Variant;
+use crate::evaluator::collections::{sorted_entries, List, Map, Vector};
+use crate::evaluator::generic::Generics;
+use crate::evaluator::lazy::LazySeq;
+use crate::evaluator::limits::Budget;
+use crate::evaluator::native::{IntoNative, Native};
+use crate::evaluator::params::Params;
+use crate::evaluator::re::Re;
+use crate::evaluator::record::Record;
+use crate::evaluator::userdata::{register_type, UserData, UserType};
 
 #[derive(Clone)]
 pub enum Value {
@@ -8,19 +25,37 @@ pub enum Value {
   String(String),
   Atom(String),
   Bool(bool),
-  // value, next
-  List(Vec<Value>),
+  // Persistent, copies share structure, see collections.rs
+  List(List),
+  Vector(Vector),
+  Map(Map),
   Do(Vec<Value>),
-  NativeFunc(fn(Vec<Value>, &mut EnvHead) -> Value),
-  Func(String, Vec<String>, Box<Value>),
+  NativeFunc(Native),
+  Func(String, Params, Rc<Value>),
+  UserData(UserData),
+  Record(Record),
+  Variant(Variant),
+  // Computed as it's walked, see lazy.rs
+  Lazy(LazySeq),
+  Regex(Re),
 }
 
 impl fmt::Debug for Value {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    return write!(f, "{}", self);
+    write!(f, "{}", self)
   }
 }
 
+fn write_seq<'a>(f: &mut fmt::Formatter, xs: impl IntoIterator<Item = &'a Value>) -> fmt::Result {
+  for (i, node) in xs.into_iter().enumerate() {
+    if i > 0 {
+      write!(f, " ")?;
+    }
+    write!(f, "{}", node)?;
+  }
+  Ok(())
+}
+
 impl fmt::Display for Value {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
@@ -30,49 +65,82 @@ impl fmt::Display for Value {
       Value::Atom(a) => write!(f, "{}", a),
       Value::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }),
       Value::Do(xs) => {
-        write!(f, "Do(");
-        for (i, node) in xs.iter().enumerate() {
-          write!(f, "{}", node);
-          if i < xs.len() - 1 {
-            write!(f, " ");
-          }
-        }
+        write!(f, "Do(")?;
+        write_seq(f, xs)?;
         write!(f, ")")
       }
       Value::List(xs) => {
-        write!(f, "List(");
-        for (i, node) in xs.iter().enumerate() {
-          write!(f, "{}", node);
-          if i < xs.len() - 1 {
-            write!(f, " ");
+        write!(f, "List(")?;
+        write_seq(f, xs)?;
+        write!(f, ")")
+      }
+      Value::Vector(xs) => {
+        write!(f, "[")?;
+        write_seq(f, xs)?;
+        write!(f, "]")
+      }
+      Value::Map(map) => {
+        write!(f, "{{")?;
+        for (i, (key, value)) in sorted_entries(map).into_iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
           }
+          write!(f, "{} {}", key, value)?;
         }
-        write!(f, ")")
+        write!(f, "}}")
       }
-      Value::NativeFunc(_) => write!(f, "NativeFunc"),
+      Value::NativeFunc(native) => write!(f, "NativeFunc({})", native.name),
       Value::Func(name, args, _progn) => {
         write!(f, "fn({} {:?})", name, args)
       }
+      Value::UserData(data) => write!(f, "{}", data),
+      Value::Record(record) => write!(f, "{}", record),
+      Value::Variant(variant) => write!(f, "{}", variant),
+      Value::Lazy(seq) => write!(f, "{}", seq),
+      Value::Regex(re) => write!(f, "{}", re),
     }
   }
 }
 
-impl PartialEq for Value {
-  fn eq(&self, other: &Value) -> bool {
-    match (self, other) {
-      (Value::Number(a), Value::Number(b)) => a == b,
-      (Value::String(a), Value::String(b)) => a == b,
-      (Value::Atom(a), Value::Atom(b)) => a == b,
-      (Value::Bool(a), Value::Bool(b)) => a == b,
-      // (Value::Func(_), Value::Func(_)) => todo!(),
-      _ => false,
-    }
+impl Value {
+  pub fn list(values: Vec<Value>) -> Value {
+    Value::List(List::from(values))
+  }
+
+  pub fn vector(values: Vec<Value>) -> Value {
+    Value::Vector(Vector::from(values))
+  }
+}
+
+// The entry of a map or the field of a record or variant for a key
+pub fn lookup<'a>(value: &'a Value, key: &Value) -> Option<&'a Value> {
+  match value {
+    Value::Map(map) => map.get(key),
+    Value::Record(record) => record.get(key),
+    Value::Variant(variant) => variant.get(key),
+    _ => None,
   }
 }
 
+// Keywords are atoms starting with a colon, they evaluate to themselves
+pub fn is_keyword(name: &str) -> bool {
+  name.len() > 1 && name.starts_with(':')
+}
+
+// Where `print` and friends write to, shared by every scope of an environment
+pub type Output = Rc<RefCell<dyn Write>>;
+
 pub struct EnvHead {
   values: HashMap<String, Value>,
   next: Option<Box<EnvHead>>,
+  out: Output,
+  err: Output,
+  budget: Rc<Budget>,
+  // Names ever bound to a special form, so the translator knows which calls take syntax
+  forms: Rc<RefCell<HashSet<String>>>,
+  generics: Generics,
+  // The generator rand/ functions use when they aren't given one
+  random: Rc<RefCell<ChaCha8Rng>>,
 }
 
 impl Clone for EnvHead {
@@ -80,30 +148,115 @@ impl Clone for EnvHead {
     Self {
       values: self.values.clone(),
       next: self.next.clone(),
+      out: self.out.clone(),
+      err: self.err.clone(),
+      budget: self.budget.clone(),
+      forms: self.forms.clone(),
+      generics: self.generics.clone(),
+      random: self.random.clone(),
     }
   }
 }
 
+impl Default for EnvHead {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl EnvHead {
   pub fn new() -> EnvHead {
+    EnvHead::with_output(Rc::new(RefCell::new(stdout())))
+  }
+
+  pub fn with_output(out: Output) -> EnvHead {
     EnvHead {
       values: HashMap::new(),
       next: None,
+      out,
+      err: Rc::new(RefCell::new(stderr())),
+      budget: Rc::new(Budget::default()),
+      forms: Rc::new(RefCell::new(HashSet::new())),
+      generics: Rc::new(RefCell::new(HashMap::new())),
+      random: Rc::new(RefCell::new(ChaCha8Rng::from_entropy())),
     }
   }
 
+  pub fn output(&self) -> Output {
+    self.out.clone()
+  }
+
+  pub fn set_output(&mut self, out: Output) {
+    self.out = out;
+  }
+
+  pub fn error_output(&self) -> Output {
+    self.err.clone()
+  }
+
+  pub fn set_error_output(&mut self, err: Output) {
+    self.err = err;
+  }
+
+  pub fn random(&self) -> Rc<RefCell<ChaCha8Rng>> {
+    self.random.clone()
+  }
+
+  // Makes the shared generator repeat the same numbers on every run
+  pub fn seed_random(&self, seed: u64) {
+    *self.random.borrow_mut() = ChaCha8Rng::seed_from_u64(seed);
+  }
+
+  pub fn budget(&self) -> &Budget {
+    &self.budget
+  }
+
+  pub fn write_out(&self, text: &str) {
+    let mut out = self.out.borrow_mut();
+    out.write_all(text.as_bytes()).unwrap();
+    out.flush().unwrap();
+  }
+
+  pub fn write_err(&self, text: &str) {
+    let mut err = self.err.borrow_mut();
+    err.write_all(text.as_bytes()).unwrap();
+    err.flush().unwrap();
+  }
+
   pub fn set(&mut self, name: String, value: Value) {
+    if let Value::NativeFunc(native) = &value {
+      if native.special {
+        self.forms.borrow_mut().insert(name.clone());
+      }
+    }
     self.values.insert(name, value);
   }
 
-  fn get_rec(&self, name: &String, env: &EnvHead) -> Option<Value> {
-    if env.values.contains_key(name) {
-      return Some(env.values[name].clone());
-    } else {
-      match &env.next {
-        Some(next) => self.get_rec(name, &next),
+  pub fn special_forms(&self) -> Rc<RefCell<HashSet<String>>> {
+    self.forms.clone()
+  }
+
+  pub fn generics(&self) -> Generics {
+    self.generics.clone()
+  }
+
+  // Binds a closure over typed arguments, see IntoNative
+  pub fn register<Args, F: IntoNative<Args>>(&mut self, name: &str, func: F) {
+    self.set(name.to_string(), Value::NativeFunc(func.into_native(name)));
+  }
+
+  // Binds the methods of a host type, values are made with UserData::new(&ty, value)
+  pub fn register_type(&mut self, ty: UserType) -> Rc<UserType> {
+    register_type(self, ty)
+  }
+
+  fn get_rec(&self, name: &str, env: &EnvHead) -> Option<Value> {
+    match env.values.get(name) {
+      Some(value) => Some(value.clone()),
+      None => match &env.next {
+        Some(next) => self.get_rec(name, next),
         _ => None,
-      }
+      },
     }
   }
 
@@ -112,16 +265,83 @@ impl EnvHead {
   }
 
   pub fn push(self) -> EnvHead {
+    let out = self.out.clone();
+    let err = self.err.clone();
+    let budget = self.budget.clone();
+    let forms = self.forms.clone();
+    let generics = self.generics.clone();
+    let random = self.random.clone();
     EnvHead {
       values: HashMap::new(),
       next: Some(Box::new(self)),
+      out,
+      err,
+      budget,
+      forms,
+      generics,
+      random,
     }
   }
 
   pub fn pop(self) -> Option<EnvHead> {
-    match self.next {
-      Some(lower) => Some(*lower),
-      _ => None,
+    self.next.map(|lower| *lower)
+  }
+
+  // push and pop for a scope that is borrowed, as the vm's is
+  pub fn push_in_place(&mut self) {
+    let lower = EnvHead {
+      values: std::mem::take(&mut self.values),
+      next: self.next.take(),
+      out: self.out.clone(),
+      err: self.err.clone(),
+      budget: self.budget.clone(),
+      forms: self.forms.clone(),
+      generics: self.generics.clone(),
+      random: self.random.clone(),
+    };
+    self.next = Some(Box::new(lower));
+  }
+
+  pub fn pop_in_place(&mut self) {
+    if let Some(lower) = self.next.take() {
+      self.values = lower.values;
+      self.next = lower.next;
+    }
+  }
+
+  // The names bound in every frame but the outermost one of globals
+  pub fn local_names(&self) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut frame = self;
+    while let Some(next) = &frame.next {
+      names.extend(frame.values.keys().cloned());
+      frame = next;
+    }
+    names
+  }
+
+  // The bindings seen from here in one frame over the outermost one. Scopes kept for later take this instead of a
+  // clone, as a call made in a clone pushes onto its whole chain and a lazy sequence built by recursion would keep
+  // a frame more for every element
+  pub fn capture(&self) -> EnvHead {
+    let mut frames = vec![self];
+    while let Some(next) = &frames[frames.len() - 1].next {
+      frames.push(next);
+    }
+    let globals = match frames.pop() {
+      Some(globals) if !frames.is_empty() => globals.clone(),
+      _ => return self.clone(),
+    };
+
+    let mut values = HashMap::new();
+    for frame in frames.iter().rev() {
+      values.extend(frame.values.iter().map(|(name, value)| (name.clone(), value.clone())));
+    }
+    EnvHead {
+      values,
+      out: self.out.clone(),
+      err: self.err.clone(),
+      ..globals.push()
     }
   }
 }
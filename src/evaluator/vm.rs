@@ -1,82 +1,271 @@
+use crate::evaluator::error::{EvalResult, HarpError};
 use crate::evaluator::opcodes::Opcode;
+use crate::evaluator::quick_eval::{apply_native, call_native, qeval_value};
 use crate::evaluator::script::Script;
-use crate::evaluator::value::Value;
+use crate::evaluator::collections::Map;
+use crate::evaluator::value::{EnvHead, Value};
+use crate::harp_err;
+use crate::translator::optimizer::OptLevel;
+use crate::translator::translator::Translator;
 
+// An active try block, where to resume and how deep the stack and scopes were when it started
+struct Handler {
+  addr: usize,
+  stack_len: usize,
+  scopes: usize,
+}
+
+#[derive(Default)]
 pub struct Vm {
   pub stack: Vec<Value>,
   pub pc: usize,
+  // Used when translating function bodies on call
+  pub opt_level: OptLevel,
+  handlers: Vec<Handler>,
+  // Scopes pushed by the running script and not popped yet
+  scopes: usize,
+}
+
+fn stack_underflow<T>() -> Result<T, HarpError> {
+  harp_err!("Stack underflow!")
 }
 
 impl Vm {
   pub fn new() -> Vm {
+    Vm::with_opt_level(OptLevel::O0)
+  }
+
+  pub fn with_opt_level(opt_level: OptLevel) -> Vm {
     Vm {
       stack: Vec::new(),
       pc: 0,
+      opt_level,
+      handlers: Vec::new(),
+      scopes: 0,
     }
   }
 
-  pub fn get_args(&mut self, num_args: usize) -> Vec<Value> {
+  pub fn get_args(&mut self, num_args: usize) -> Result<Vec<Value>, HarpError> {
     let mut results = Vec::new();
     while results.len() < num_args {
       match self.stack.pop() {
         Some(v) => results.push(v),
-        None => panic!("Stack underflow!"),
+        None => return stack_underflow(),
+      }
+    }
+    results.reverse();
+    Ok(results)
+  }
+
+  fn peek(&self) -> EvalResult {
+    match self.stack.last() {
+      Some(value) => Ok(value.clone()),
+      None => stack_underflow(),
+    }
+  }
+
+  fn call(&mut self, env: &mut EnvHead, callee: Value, args: Vec<Value>) -> EvalResult {
+    match callee {
+      Value::NativeFunc(native) => apply_native(&native, args, env),
+      Value::Func(name, params, progn) => {
+        let mut scope = env.clone().push();
+        params.bind(&name, args, &mut scope)?;
+
+        env.budget().enter()?;
+        let result = Vm::with_opt_level(self.opt_level).eval(&mut scope, Value::clone(&progn));
+        env.budget().leave();
+        result
       }
+      v => harp_err!("Cannot function call on function {}", v),
     }
-    return results;
   }
 
-  pub fn eval(&mut self, env: &mut Value, value: Value) -> Value {
+  pub fn eval(&mut self, env: &mut EnvHead, value: Value) -> EvalResult {
     match value {
-      Value::Number(_) | Value::String(_) | Value::Bool(_) | Value::Unit | Value::NativeFunc(_) => {
-        value
+      Value::Number(_)
+      | Value::String(_)
+      | Value::Bool(_)
+      | Value::Unit
+      | Value::NativeFunc(_)
+      | Value::Func(_, _, _)
+      | Value::UserData(_)
+      | Value::Record(_)
+      | Value::Variant(_)
+      | Value::Lazy(_)
+      | Value::Regex(_) => Ok(value),
+      _ => {
+        let script = Translator::for_env(self.opt_level, env).value_to_script(&value);
+        self.eval_script(env, &script)
       }
-      // Value::Atom(name) => match get_value_from_env(&name, env) {
-      //   Some(value) => value,
-      //   None => Value::Unit,
-      // },
-      _ => panic!("Unhandled value"),
     }
   }
 
-  pub fn eval_script(&mut self, env: &mut Value, script: Script) -> Value {
-    while self.pc < script.instructions.len() {
-      let opcode = &script.instructions[self.pc];
+  fn step(&mut self, env: &mut EnvHead, script: &Script, opcode: &Opcode) -> Result<(), HarpError> {
+    env.budget().step()?;
 
-      match opcode {
-        Opcode::Push(value) => {
-          self.stack.push(value.clone());
-          self.pc += 1;
+    match opcode {
+      Opcode::Push(value) => self.stack.push(value.clone()),
+
+      Opcode::Pop => {
+        self.stack.pop();
+      }
+
+      Opcode::Const(index) => self.stack.push(script.constants[*index].clone()),
+
+      Opcode::Load(index) => match &script.constants[*index] {
+        Value::Atom(name) => match env.get(name.clone()) {
+          Some(value) => self.stack.push(value),
+          None => {
+            env.write_err(&format!("Undefind Variable {}\n", name));
+            self.stack.push(Value::Unit)
+          }
+        },
+        v => return harp_err!("Load expected an identifier, but got: {}", v),
+      },
+
+      Opcode::Define(index) => match &script.constants[*index] {
+        Value::Atom(name) => {
+          if env.get(name.clone()).is_some() {
+            return harp_err!("{} is already defined", name);
+          }
+          let value = self.peek()?;
+          env.budget().alloc(&value)?;
+          env.set(name.clone(), value);
         }
+        v => return harp_err!("Def expected an identifier, but got: {}", v),
+      },
 
-        Opcode::Const(index) => {
-          self.stack.push(script.constants[*index].clone());
-          self.pc += 1
+      Opcode::Set(index) => match &script.constants[*index] {
+        Value::Atom(name) => {
+          let value = self.peek()?;
+          env.budget().alloc(&value)?;
+          env.set(name.clone(), value)
         }
+        v => return harp_err!("Set expected an identifier, but got: {}", v),
+      },
+
+      Opcode::Call(num_args) => {
+        let callee = match self.stack.pop() {
+          Some(callee) => callee,
+          None => return stack_underflow(),
+        };
+        let args = self.get_args(*num_args)?;
+        let result = self.call(env, callee, args)?;
+        self.stack.push(result);
+      }
 
-        Opcode::Call(num_args) => {
-          let atom = self.stack.pop();
-          match atom {
-            // Some(Value::Atom(lexeme)) => match get_value_from_env(&lexeme, env) {
-            //   Some(Value::NativeFunc(callable)) => {
-            //     let args = self.get_args(*num_args);
-            //     // let result = callable(self, args, env);
-            //     // self.stack.push(result);
-            //   }
-            //   None => panic!("Function {:?} does not exist in environment", lexeme),
-            //   _ => panic!("'{:?}' is not a callable value", lexeme),
-            // },
-            _ => panic!("Failed to call function"),
+      // The arguments are syntax, pushed as is
+      Opcode::CallSpecial(num_args) => {
+        let callee = match self.stack.pop() {
+          Some(callee) => callee,
+          None => return stack_underflow(),
+        };
+        let args = self.get_args(*num_args)?;
+        let result = match callee {
+          Value::NativeFunc(native) => call_native(&native, args.iter(), env)?,
+          callee => {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+              values.push(qeval_value(arg, env)?);
+            }
+            self.call(env, callee, values)?
           }
-          self.pc += 1;
+        };
+        self.stack.push(result);
+      }
+
+      Opcode::Vector(len) => {
+        let xs = self.get_args(*len)?;
+        self.stack.push(Value::vector(xs));
+      }
+
+      Opcode::Map(len) => {
+        let mut map = Map::default();
+        let mut xs = self.get_args(len * 2)?.into_iter();
+        while let (Some(key), Some(value)) = (xs.next(), xs.next()) {
+          map.insert(key, value);
+        }
+        self.stack.push(Value::Map(map));
+      }
+
+      Opcode::Jump(addr) => self.pc = *addr,
+
+      Opcode::JumpTable(table) => self.pc = table.target(&self.peek()?),
+
+      Opcode::JumpIfFalse(addr) => match self.stack.pop() {
+        Some(Value::Bool(true)) => {}
+        Some(Value::Bool(false)) => self.pc = *addr,
+        Some(v) => {
+          return harp_err!(
+            "If expected its expression to evaluate to boolean, but got {}",
+            v
+          )
         }
-        _ => todo!(),
+        None => return stack_underflow(),
+      },
+
+      Opcode::Try(addr) => self.handlers.push(Handler {
+        addr: *addr,
+        stack_len: self.stack.len(),
+        scopes: self.scopes,
+      }),
+
+      Opcode::EndTry => {
+        self.handlers.pop();
+      }
+
+      Opcode::PushScope => {
+        env.push_in_place();
+        self.scopes += 1;
+      }
+
+      Opcode::PopScope => self.pop_scopes(env, self.scopes - 1),
+    }
+
+    Ok(())
+  }
+
+  // Leaves the scopes pushed after there were depth of them
+  fn pop_scopes(&mut self, env: &mut EnvHead, depth: usize) {
+    while self.scopes > depth {
+      env.pop_in_place();
+      self.scopes -= 1;
+    }
+  }
+
+  pub fn eval_script(&mut self, env: &mut EnvHead, script: &Script) -> EvalResult {
+    self.pc = 0;
+    self.handlers.clear();
+    self.scopes = 0;
+
+    while self.pc < script.instructions.len() {
+      let opcode = &script.instructions[self.pc];
+      self.pc += 1;
+
+      match self.step(env, script, opcode) {
+        Ok(()) => {}
+        Err(HarpError::Interrupted) => {
+          self.pop_scopes(env, 0);
+          return Err(HarpError::Interrupted);
+        }
+        Err(err) => match self.handlers.pop() {
+          Some(handler) => {
+            self.pop_scopes(env, handler.scopes);
+            self.stack.truncate(handler.stack_len);
+            self.stack.push(Value::String(err.message()));
+            self.pc = handler.addr;
+          }
+          None => {
+            self.pop_scopes(env, 0);
+            return Err(err);
+          }
+        },
       }
     }
 
     match self.stack.pop() {
-      Some(value) => value,
-      None => Value::Unit,
+      Some(value) => Ok(value),
+      None => Ok(Value::Unit),
     }
   }
 }
@@ -0,0 +1,25 @@
+/*
+	Harp as a library. The modules are public for tools that need the internals (the reader's ast, the translator),
+	the re-exports below are the stable surface for embedding. Most hosts only need Interpreter.
+*/
+
+pub mod common;
+pub mod evaluator;
+pub mod reader;
+pub mod translator;
+
+pub use common::prelude::{make_std_env, make_std_env_with_output};
+pub use evaluator::error::{EvalResult, HarpError};
+pub use evaluator::interpreter::Interpreter;
+pub use evaluator::lazy::LazySeq;
+pub use evaluator::limits::{Budget, Limits};
+pub use evaluator::re::Re;
+pub use evaluator::native::{Arity, FromValue, IntoNative, IntoValue, Native};
+pub use evaluator::userdata::{UserData, UserType};
+pub use evaluator::quick_eval::{qapply, qeval_expr, qeval_progn, qeval_value};
+pub use evaluator::value::{EnvHead, Output, Value};
+pub use evaluator::vm::Vm;
+pub use reader::ast::Node;
+pub use reader::reader::Reader;
+pub use translator::optimizer::OptLevel;
+pub use translator::translator::Translator;
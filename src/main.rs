@@ -1,32 +1,69 @@
 use std::env;
 use std::fs;
+use std::process;
+use std::sync::atomic::Ordering;
 
-use crate::evaluator::quick_eval::*;
-use crate::evaluator::value::*;
-
-use crate::common::prelude::make_std_env;
+use harp::evaluator::differential::compare_source;
+use harp::{make_std_env, qeval_progn, EnvHead, EvalResult, HarpError, Limits, OptLevel, Reader, Translator, Vm};
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-mod common;
-mod evaluator;
-mod reader;
-mod translator;
+struct Options {
+    opt_level: OptLevel,
+    limits: Limits,
+    seed: Option<u64>,
+}
+
+impl Options {
+    fn apply(&self, env: &EnvHead) {
+        env.budget().set_limits(self.limits);
+        if let Some(seed) = self.seed {
+            env.seed_random(seed);
+        }
+    }
+}
 
-fn repl() {
+fn report(result: EvalResult) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(HarpError::Interrupted) => {
+            println!("Interrupted");
+            false
+        }
+        Err(err) => {
+            println!("Error: {}", err);
+            false
+        }
+    }
+}
+
+fn repl(options: &Options) {
     const HIST: &str = "harp-repl-history";
 
     let mut std_env = make_std_env();
+    options.apply(&std_env);
     let mut rl = Editor::<()>::new();
 
-    if rl.load_history(HIST).is_err() {}
+    // Ctrl-C at the prompt is read by rustyline, while evaluating it stops the evaluation
+    let interrupt = std_env.budget().interrupt_flag();
+    if let Err(err) = ctrlc::set_handler(move || interrupt.store(true, Ordering::SeqCst)) {
+        println!("Failed to install the Ctrl-C handler: {}", err);
+    }
+
+    rl.load_history(HIST).ok();
 
     loop {
         match rl.readline("> ") {
             Ok(line) => {
-                let ast = reader::reader::Reader::new(&line).next_progn();
-                println!("{}", qeval_progn(&ast, &mut std_env));
+                std_env.budget().reset();
+                let result = Reader::new(&line).next_progn().and_then(|ast| qeval_progn(&ast, &mut std_env));
+                match result {
+                    Ok(value) => println!("{}", value),
+                    err => {
+                        report(err);
+                    }
+                }
             }
 
             Err(ReadlineError::Interrupted) => {
@@ -48,13 +85,18 @@ fn repl() {
     rl.save_history(HIST).unwrap();
 }
 
-fn run_script(path: &String) {
+fn run_script(path: &str, options: &Options) {
     match fs::read_to_string(path) {
         Ok(s) => {
             let mut std_env = make_std_env();
-            let progn = reader::reader::Reader::new(&s).next_progn();
-            println!("AST: {}", progn);
-            qeval_progn(&progn, &mut std_env);
+            options.apply(&std_env);
+            let result = Reader::new(&s).next_progn().and_then(|progn| {
+                println!("AST: {}", progn);
+                qeval_progn(&progn, &mut std_env)
+            });
+            if !report(result) {
+                process::exit(1);
+            }
         }
         Err(err) => panic!("{}", err),
     }
@@ -62,16 +104,96 @@ fn run_script(path: &String) {
     // qeval_progn(progn: &Node, env: &mut EnvHead)
 }
 
+fn run_script_vm(path: &str, options: &Options) {
+    match fs::read_to_string(path) {
+        Ok(s) => {
+            let mut std_env = make_std_env();
+            options.apply(&std_env);
+            let result = Reader::new(&s).next_progn().and_then(|progn| {
+                let script = Translator::for_env(options.opt_level, &std_env).progn_to_script(progn);
+                Vm::with_opt_level(options.opt_level).eval_script(&mut std_env, &script)
+            });
+            if !report(result) {
+                process::exit(1);
+            }
+        }
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn diff_script(path: &str, options: &Options) {
+    match fs::read_to_string(path) {
+        Ok(s) => match compare_source(&s, options.opt_level) {
+            Ok(forms) => println!("{}: {} forms, no divergences", path, forms),
+            Err(divergence) => {
+                println!("{}: {}", path, divergence);
+                process::exit(1);
+            }
+        },
+        Err(err) => panic!("{}", err),
+    }
+}
+
 fn help() {
-    println!("Harp Help")
+    println!("Harp Help");
+    println!("  harp                 start the repl");
+    println!("  harp <file>          run a script");
+    println!("  harp --vm <file>     run a script on the vm");
+    println!("  harp --diff <file>   compare quick_eval and the vm on a script");
+    println!("  -O0, -O1, -O2        optimization level for --vm and --diff (default -O0)");
+    println!("  --max-steps=N        stop evaluations taking more than N steps");
+    println!("  --max-depth=N        stop evaluations nesting more than N calls (default 200)");
+    println!("  --max-values=N       stop evaluations allocating more than N values");
+    println!("  --seed=N             seed the random number generator, for runs that repeat");
+}
+
+// Takes the flags out of the arguments, leaving the positional ones
+fn parse_options(args: Vec<String>) -> (Options, Vec<String>) {
+    let mut options = Options {
+        opt_level: OptLevel::O0,
+        limits: Limits::default(),
+        seed: None,
+    };
+
+    let parse_limit = |arg: &str, flag: &str| -> Option<usize> {
+        let n = arg.strip_prefix(flag)?;
+        match n.parse() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                println!("Expected a number for {}, but got {}", flag, n);
+                process::exit(1);
+            }
+        }
+    };
+
+    let mut rest = Vec::new();
+    for arg in args {
+        if let Some(level) = OptLevel::from_flag(&arg) {
+            options.opt_level = level;
+        } else if let Some(n) = parse_limit(&arg, "--max-steps=") {
+            options.limits.max_steps = Some(n as u64);
+        } else if let Some(n) = parse_limit(&arg, "--max-depth=") {
+            options.limits.max_depth = Some(n);
+        } else if let Some(n) = parse_limit(&arg, "--max-values=") {
+            options.limits.max_values = Some(n);
+        } else if let Some(n) = parse_limit(&arg, "--seed=") {
+            options.seed = Some(n as u64);
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    (options, rest)
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (options, args) = parse_options(env::args().collect());
 
     match args.len() {
-        1 => repl(),
-        2 => run_script(&args[1]),
+        1 => repl(&options),
+        2 => run_script(&args[1], &options),
+        3 if args[1] == "--vm" => run_script_vm(&args[2], &options),
+        3 if args[1] == "--diff" => diff_script(&args[2], &options),
         _ => help(),
     }
 }
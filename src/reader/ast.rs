@@ -1,5 +1,6 @@
 use super::super::evaluator::value::Value;
-use super::super::reader::reader::{Loc, Tok};
+use super::super::reader::reader::Loc;
+use crate::evaluator::re::Re;
 use std::fmt::*;
 
 pub const QUOTED: u8 = 0b00000001;
@@ -22,6 +23,12 @@ impl Display for NodeInfo {
   }
 }
 
+impl Default for NodeInfo {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl NodeInfo {
   pub fn new() -> NodeInfo {
     NodeInfo {
@@ -31,10 +38,7 @@ impl NodeInfo {
   }
 
   pub fn loc(loc: Loc) -> NodeInfo {
-    NodeInfo {
-      flags: 0u8,
-      loc: loc,
-    }
+    NodeInfo { flags: 0u8, loc }
   }
 
   pub fn new_flags(flags: u8) -> NodeInfo {
@@ -43,6 +47,10 @@ impl NodeInfo {
       loc: Loc::blank(),
     }
   }
+
+  pub fn is_quoted(&self) -> bool {
+    self.flags & QUOTED != 0
+  }
 }
 
 #[derive(Debug, PartialEq)]
@@ -52,8 +60,44 @@ pub enum Node {
   StringLit(String, NodeInfo),
   NumberLit(f64, NodeInfo),
   BoolLit(bool, NodeInfo),
+  RegexLit(Re, NodeInfo),
   Progn(Vec<Node>, NodeInfo),
   List(Vec<Node>, NodeInfo),
+  Vector(Vec<Node>, NodeInfo),
+  // Keys and values alternate
+  Map(Vec<Node>, NodeInfo),
+}
+
+impl Node {
+  pub fn info(&self) -> &NodeInfo {
+    match self {
+      Node::Unit(i)
+      | Node::AtomLit(_, i)
+      | Node::StringLit(_, i)
+      | Node::NumberLit(_, i)
+      | Node::BoolLit(_, i)
+      | Node::RegexLit(_, i)
+      | Node::Progn(_, i)
+      | Node::List(_, i)
+      | Node::Vector(_, i)
+      | Node::Map(_, i) => i,
+    }
+  }
+
+  pub fn info_mut(&mut self) -> &mut NodeInfo {
+    match self {
+      Node::Unit(i)
+      | Node::AtomLit(_, i)
+      | Node::StringLit(_, i)
+      | Node::NumberLit(_, i)
+      | Node::BoolLit(_, i)
+      | Node::RegexLit(_, i)
+      | Node::Progn(_, i)
+      | Node::List(_, i)
+      | Node::Vector(_, i)
+      | Node::Map(_, i) => i,
+    }
+  }
 }
 
 pub fn to_str(
@@ -67,40 +111,55 @@ pub fn to_str(
     Node::StringLit(s, i) => write!(f, "{}:{} S: {}", i, indent, s),
     Node::NumberLit(n, i) => write!(f, "{}:{} N: {}", i, indent, n),
     Node::BoolLit(b, i) => write!(f, "{}:{} B: {}", i, indent, b),
+    Node::RegexLit(re, i) => write!(f, "{}:{} R: {}", i, indent, re),
     Node::Progn(ns, i) => {
-      write!(f, "{}:{} Progn:\n", i, indent);
+      writeln!(f, "{}:{} Progn:", i, indent)?;
       for n in ns {
         let mut next_indent = String::from("  ");
-        next_indent.push_str(&indent.to_string());
-        to_str(f, n, next_indent.to_string()).unwrap();
-        write!(f, "\n");
+        next_indent.push_str(&indent);
+        to_str(f, n, next_indent)?;
+        writeln!(f)?;
       }
-      write!(f, "")
+      Ok(())
     }
-    Node::List(ns, i) => {
-      write!(f, "{}:{} List:\n", i, indent);
+    Node::List(ns, i) | Node::Vector(ns, i) | Node::Map(ns, i) => {
+      let kind = match node {
+        Node::Vector(_, _) => "Vector",
+        Node::Map(_, _) => "Map",
+        _ => "List",
+      };
+      writeln!(f, "{}:{} {}:", i, indent, kind)?;
       for n in ns {
         let mut next_indent = String::from("  ");
-        next_indent.push_str(&indent.to_string());
-        to_str(f, n, next_indent.to_string()).unwrap();
-        write!(f, "\n");
+        next_indent.push_str(&indent);
+        to_str(f, n, next_indent)?;
+        writeln!(f)?;
       }
-      write!(f, "")
+      Ok(())
     }
   }
 }
 
 pub fn to_value(node: &Node) -> Value {
-  match node {
+  let value = match node {
     Node::Unit(_) => Value::Unit,
     Node::AtomLit(s, _) => Value::Atom(s.clone()),
     Node::StringLit(s, _) => Value::String(s.clone()),
-    Node::NumberLit(n, _) => Value::Number(n.clone()),
-    Node::BoolLit(b, _) => Value::Bool(b.clone()),
-    Node::List(xs, _) => Value::List(xs.iter().map(|n| to_value(n)).collect()),
+    Node::NumberLit(n, _) => Value::Number(*n),
+    Node::BoolLit(b, _) => Value::Bool(*b),
+    Node::RegexLit(re, _) => Value::Regex(re.clone()),
+    Node::List(xs, _) => Value::List(xs.iter().map(to_value).collect()),
+    Node::Vector(xs, _) => Value::Vector(xs.iter().map(to_value).collect()),
+    Node::Map(xs, _) => Value::Map(xs.chunks(2).map(|kv| (to_value(&kv[0]), to_value(&kv[1]))).collect()),
     v => panic!("Not supported yet '{}'", v),
     // Progn(Vec<Node>, NodeInfo) =>
     // Node::List(xs, _) => {}
+  };
+
+  if node.info().is_quoted() {
+    Value::list(vec![Value::Atom("quote".to_string()), value])
+  } else {
+    value
   }
 }
 
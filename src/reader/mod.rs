@@ -1,7 +1,10 @@
 pub mod ast;
+#[allow(clippy::module_inception)]
 pub mod reader;
 
-use ast::{Node, NodeInfo};
+#[cfg(test)]
+use ast::{to_value, Node, QUOTED};
+#[cfg(test)]
 use reader::{Loc, Reader, Tok};
 
 #[test]
@@ -10,10 +13,11 @@ fn reader_skip_whitespace_test() {
   lexer.skip_whitespace();
   let chr = lexer.current_char_or('\0');
   assert_eq!(chr, 'X');
-  assert_eq!('\0'.is_numeric(), false);
+  assert!(!'\0'.is_numeric());
 }
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn reader_number_literal_test() {
   let mut lexer = Reader::new("3.14159 -32.1 .41 -.123 #f");
 
@@ -90,3 +94,75 @@ fn reader_atom_test() {
     lexer.next_token()
   );
 }
+
+#[test]
+fn reader_quote_test() {
+  let mut reader = Reader::new("'(a b) 'c");
+
+  match reader.next_expr().unwrap() {
+    Node::List(xs, info) => {
+      assert_eq!(xs.len(), 2);
+      assert_eq!(info.flags & QUOTED, QUOTED);
+    }
+    n => panic!("Expected a quoted list, got {}", n),
+  }
+
+  assert_eq!(
+    format!("{}", to_value(&reader.next_expr().unwrap())),
+    "List(quote c)"
+  );
+}
+
+#[test]
+fn reader_syntax_error_test() {
+  for code in ["(print 1", "(print 1 ", "1 )", "'", "`x", "[1 2)", "{:a}", "(1]"].iter() {
+    match Reader::new(code).next_progn() {
+      Err(crate::evaluator::error::HarpError::Syntax(_)) => {}
+      res => panic!("Expected a syntax error for {:?}, got {:?}", code, res),
+    }
+  }
+}
+
+#[test]
+fn reader_consecutive_comments_test() {
+  let mut lexer = Reader::new(";; one\n;; two\n\n  ; three\n42");
+  assert_eq!(Tok::Number(42.0, Loc::blank()), lexer.next_token());
+}
+
+#[test]
+fn reader_collections_test() {
+  let mut reader = Reader::new("[1 [a]] {:x 1 \"y\" (f)}");
+  assert_eq!(format!("{}", to_value(&reader.next_expr().unwrap())), "[1 [a]]");
+  assert_eq!(
+    format!("{}", to_value(&reader.next_expr().unwrap())),
+    "{y List(f) :x 1}"
+  );
+}
+
+#[test]
+fn reader_interpolation_test() {
+  let mut reader = Reader::new(
+    "#\"Hi {name}!\" #\"{(f {:a 1})}\" #\"{(str/upper \"x\")}!\" #\"{{{(f \"}\")}}}\" #\"{a b}\" #\"}\" #\"{\"",
+  );
+  assert_eq!(format!("{}", to_value(&reader.next_expr().unwrap())), "List(str Hi  name !)");
+  assert_eq!(format!("{}", to_value(&reader.next_expr().unwrap())), "List(str List(f {:a 1}))");
+  // Quotes in a placeholder are strings of its expression
+  assert_eq!(format!("{}", to_value(&reader.next_expr().unwrap())), "List(str List(str/upper x) !)");
+  assert_eq!(format!("{}", to_value(&reader.next_expr().unwrap())), "List(str { List(f }) })");
+  for _ in 0..3 {
+    match reader.next_expr() {
+      Err(crate::evaluator::error::HarpError::Syntax(_)) => {}
+      res => panic!("Expected a syntax error, got {:?}", res),
+    }
+  }
+}
+
+#[test]
+fn reader_regex_literal_test() {
+  let mut reader = Reader::new("#r\"\\d+\" #r\"(\"");
+  assert_eq!(format!("{}", to_value(&reader.next_expr().unwrap())), "#r\"\\d+\"");
+  assert!(matches!(
+    reader.next_expr(),
+    Err(crate::evaluator::error::HarpError::Syntax(_))
+  ));
+}
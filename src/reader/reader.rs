@@ -1,4 +1,6 @@
+use super::super::evaluator::error::HarpError;
 use super::super::reader::ast::*;
+use crate::evaluator::re::Re;
 
 #[derive(Debug, PartialEq)]
 pub struct Loc {
@@ -18,6 +20,10 @@ pub enum Tok {
   Number(f64, Loc),
   Bool(bool, Loc),
   Str(String, Loc),
+  // #"..." with {expr} placeholders
+  Interp(String, Loc),
+  // #r"...", compiled as it's read
+  Regex(String, Loc),
   Atom(String, Loc), // TODO(Dustin): Create an atom dictionary and only store an atom ID
   OpenParen(Loc),
   CloseParen(Loc),
@@ -26,6 +32,7 @@ pub enum Tok {
   OpenBracket(Loc),
   CloseBracket(Loc),
   Quote(Loc),
+  Unknown(char, Loc),
 }
 
 impl PartialEq for Tok {
@@ -33,7 +40,9 @@ impl PartialEq for Tok {
     match (self, other) {
       (Tok::Eof(_), Tok::Eof(_)) => true,
       (Tok::Number(a, _), Tok::Number(b, _)) => a == b,
-      (Tok::Str(a, _), Tok::Str(b, _)) => a == b,
+      (Tok::Str(a, _), Tok::Str(b, _)) | (Tok::Interp(a, _), Tok::Interp(b, _)) | (Tok::Regex(a, _), Tok::Regex(b, _)) => {
+        a == b
+      }
       (Tok::Atom(a, _), Tok::Atom(b, _)) => a == b,
       (Tok::Bool(a, _), Tok::Bool(b, _)) => a == b,
       (Tok::OpenParen(_), Tok::OpenParen(_)) => true,
@@ -43,11 +52,67 @@ impl PartialEq for Tok {
       (Tok::OpenBracket(_), Tok::OpenBracket(_)) => true,
       (Tok::CloseBracket(_), Tok::CloseBracket(_)) => true,
       (Tok::Quote(_), Tok::Quote(_)) => true,
+      (Tok::Unknown(a, _), Tok::Unknown(b, _)) => a == b,
       _ => false,
     }
   }
 }
 
+fn syntax_error(loc: &Loc, message: &str) -> HarpError {
+  HarpError::Syntax(format!("{} (line {}, column {})", message, loc.line, loc.column))
+}
+
+// #"Hello {name}!" reads as (str "Hello " name "!"), {{ and }} stand for braces. Placeholders can hold strings, as in
+// #"{(str/upper "x")}", and braces in those strings don't count
+fn interpolation(text: &str, loc: Loc) -> Result<Node, HarpError> {
+  let mut parts = vec![Node::AtomLit("str".to_string(), NodeInfo::loc(Loc { ..loc }))];
+  let mut literal = String::new();
+  let mut chars = text.chars().peekable();
+  while let Some(chr) = chars.next() {
+    match chr {
+      '{' | '}' if chars.peek() == Some(&chr) => {
+        chars.next();
+        literal.push(chr);
+      }
+      '{' => {
+        let mut code = String::new();
+        let (mut depth, mut quoted) = (0, false);
+        loop {
+          match chars.next() {
+            Some('}') if depth == 0 && !quoted => break,
+            Some(c) => {
+              match c {
+                '"' => quoted = !quoted,
+                '{' if !quoted => depth += 1,
+                '}' if !quoted => depth -= 1,
+                _ => {}
+              }
+              code.push(c);
+            }
+            None => return Err(syntax_error(&loc, "Expected '}' to close a placeholder of an interpolated string")),
+          }
+        }
+        if !literal.is_empty() {
+          parts.push(Node::StringLit(std::mem::take(&mut literal), NodeInfo::loc(Loc { ..loc })));
+        }
+
+        let mut reader = Reader::new(&code);
+        let expr = reader.next_expr()?;
+        if matches!(expr, Node::Unit(_)) || !matches!(reader.next_expr()?, Node::Unit(_)) {
+          return Err(syntax_error(&loc, &format!("Expected one expression in {{{}}}", code)));
+        }
+        parts.push(expr);
+      }
+      '}' => return Err(syntax_error(&loc, "Unexpected '}' in an interpolated string, write }} for a brace")),
+      chr => literal.push(chr),
+    }
+  }
+  if !literal.is_empty() {
+    parts.push(Node::StringLit(literal, NodeInfo::loc(Loc { ..loc })));
+  }
+  Ok(Node::List(parts, NodeInfo::loc(loc)))
+}
+
 pub struct Reader {
   it: usize,
   pin: usize,
@@ -95,14 +160,11 @@ impl Reader {
   }
 
   pub fn current_char_or(&self, or: char) -> char {
-    if self.at_eof() {
-      return or;
-    }
-    return self.code[self.it];
+    self.current_char().unwrap_or(or)
   }
 
   pub fn current_char_def(&self) -> char {
-    return self.current_char_or('\0');
+    self.current_char_or('\0')
   }
 
   pub fn current_char(&self) -> Option<char> {
@@ -121,7 +183,7 @@ impl Reader {
   fn get_then_move(&mut self) -> char {
     let v = self.current_char_def();
     self.move_next();
-    return v;
+    v
   }
 
   pub fn skip_whitespace(&mut self) {
@@ -145,10 +207,47 @@ impl Reader {
     }
   }
 
+  // The characters up to the closing quote, starting at the opening one
+  fn string_body(&mut self) -> String {
+    let mut builder = String::new();
+    self.move_next();
+    while !self.at_eof() {
+      let chr = self.get_then_move();
+      if chr == '\"' {
+        break;
+      } else {
+        builder.push(chr);
+      }
+    }
+    builder
+  }
+
+  // The same for #"...", where a quote in a {placeholder} starts a string of its expression instead of ending this one
+  fn interpolation_body(&mut self) -> String {
+    let mut builder = String::new();
+    let (mut depth, mut quoted) = (0, false);
+    self.move_next();
+    while !self.at_eof() {
+      let chr = self.get_then_move();
+      match chr {
+        '\"' if depth == 0 => break,
+        '\"' => quoted = !quoted,
+        '{' | '}' if depth == 0 && self.current_char_def() == chr => builder.push(self.get_then_move()),
+        '{' if !quoted => depth += 1,
+        '}' if !quoted && depth > 0 => depth -= 1,
+        _ => {}
+      }
+      builder.push(chr);
+    }
+    builder
+  }
+
   pub fn next_token(&mut self) -> Tok {
     self.skip_whitespace();
-    self.skip_comments();
-    self.skip_whitespace();
+    while self.current_char_def() == ';' {
+      self.skip_comments();
+      self.skip_whitespace();
+    }
 
     let mut builder = String::new();
 
@@ -156,10 +255,7 @@ impl Reader {
     if self.current_char_def() == '-' {
       builder.push('-');
       self.move_next();
-      true
-    } else {
-      false
-    };
+    }
 
     let mut is_dec = if self.current_char_def() == '.' {
       builder.push('.');
@@ -202,23 +298,18 @@ impl Reader {
       } else if self.current_char_def() == 't' {
         self.move_next();
         return Tok::Bool(true, self.get_loc());
+      } else if self.current_char_def() == '\"' {
+        return Tok::Interp(self.interpolation_body(), self.get_loc());
+      } else if self.current_char_def() == 'r' && self.code.get(self.it + 1) == Some(&'\"') {
+        self.move_next();
+        return Tok::Regex(self.string_body(), self.get_loc());
       }
       self.unpin();
     }
 
     // String literals
     if self.current_char_def() == '\"' {
-      self.move_next();
-      while !self.at_eof() {
-        let chr = self.get_then_move();
-        if chr == '\"' {
-          break;
-        } else {
-          builder.push(chr);
-        }
-      }
-
-      return Tok::Str(builder.clone(), self.get_loc());
+      return Tok::Str(self.string_body(), self.get_loc());
     }
 
     match self.current_char_def() {
@@ -261,69 +352,103 @@ impl Reader {
       builder.push(self.get_then_move());
     }
 
-    if builder.len() > 0 {
+    if !builder.is_empty() {
       return Tok::Atom(builder.clone(), self.get_loc());
     }
 
-    panic!("Unexpected character: '{:?}'", self.current_char_def());
+    let chr = self.get_then_move();
+    Tok::Unknown(chr, self.get_loc())
   }
 
-  pub fn next_expr(&mut self) -> Node {
-    let mut tok = self.next_token();
+  // Reads the next expression, or a unit node at the end of the input
+  pub fn next_expr(&mut self) -> Result<Node, HarpError> {
+    let tok = self.next_token();
+    self.expr_from(tok)
+  }
 
-    let mut quoted = false;
-    match tok {
-      Tok::Quote(_) => {
-        quoted = true;
-        tok = self.next_token();
-      }
-      _ => {}
+  fn expr_from(&mut self, tok: Tok) -> Result<Node, HarpError> {
+    if let Tok::Quote(loc) = tok {
+      let mut node = match self.next_token() {
+        Tok::Eof(_) | Tok::CloseParen(_) => {
+          return Err(syntax_error(&loc, "Expected an expression after quote"))
+        }
+        tok => self.expr_from(tok)?,
+      };
+      node.info_mut().flags |= QUOTED;
+      return Ok(node);
     }
 
-    return match tok {
-      Tok::Eof(loc) => Node::Unit(NodeInfo::loc(loc)),
-
-      Tok::Atom(a, loc) => Node::AtomLit(a, NodeInfo::loc(loc)),
-      Tok::Number(n, loc) => Node::NumberLit(n, NodeInfo::loc(loc)),
-      Tok::Str(s, loc) => Node::StringLit(s, NodeInfo::loc(loc)),
-      Tok::Bool(b, loc) => Node::BoolLit(b, NodeInfo::loc(loc)),
+    match tok {
+      Tok::Eof(loc) => Ok(Node::Unit(NodeInfo::loc(loc))),
+
+      Tok::Atom(a, loc) => Ok(Node::AtomLit(a, NodeInfo::loc(loc))),
+      Tok::Number(n, loc) => Ok(Node::NumberLit(n, NodeInfo::loc(loc))),
+      Tok::Str(s, loc) => Ok(Node::StringLit(s, NodeInfo::loc(loc))),
+      Tok::Interp(s, loc) => interpolation(&s, loc),
+      Tok::Regex(s, loc) => match Re::new(&s) {
+        Ok(re) => Ok(Node::RegexLit(re, NodeInfo::loc(loc))),
+        Err(err) => Err(syntax_error(&loc, &format!("Invalid regular expression: {}", err))),
+      },
+      Tok::Bool(b, loc) => Ok(Node::BoolLit(b, NodeInfo::loc(loc))),
 
       Tok::OpenParen(loc) => {
-        let mut ns = Vec::<Node>::new();
-        while !self.at_eof() {
-          let sub_node = self.next_expr();
-          match sub_node {
-            Node::Unit(_) => return Node::List(ns, NodeInfo::loc(loc)),
-            _ => ns.push(sub_node),
-          };
+        let ns = self.read_until(&loc, ')')?;
+        Ok(Node::List(ns, NodeInfo::loc(loc)))
+      }
+      Tok::OpenBracket(loc) => {
+        let ns = self.read_until(&loc, ']')?;
+        Ok(Node::Vector(ns, NodeInfo::loc(loc)))
+      }
+      Tok::OpenBrace(loc) => {
+        let ns = self.read_until(&loc, '}')?;
+        if ns.len() % 2 != 0 {
+          return Err(syntax_error(&loc, "A map literal needs a value for every key"));
         }
+        Ok(Node::Map(ns, NodeInfo::loc(loc)))
+      }
 
-        if self.at_eof() {
-          panic!("Unbalanced braces starting at line: {:?}", loc.line)
-        }
+      Tok::CloseParen(ref loc) => Err(syntax_error(loc, "Unexpected ')'")),
+      Tok::CloseBracket(ref loc) => Err(syntax_error(loc, "Unexpected ']'")),
+      Tok::CloseBrace(ref loc) => Err(syntax_error(loc, "Unexpected '}'")),
 
-        return Node::List(ns, NodeInfo::loc(loc));
-      }
+      Tok::Unknown(chr, ref loc) => Err(syntax_error(loc, &format!("Unexpected character: '{}'", chr))),
 
-      Tok::CloseParen(loc) => return Node::Unit(NodeInfo::loc(loc)),
+      Tok::Quote(_) => unreachable!(),
+    }
+  }
 
-      _ => panic!("What? {:?}", tok),
-    };
+  // Reads expressions up to the closing character of a list, vector or map
+  fn read_until(&mut self, loc: &Loc, close: char) -> Result<Vec<Node>, HarpError> {
+    let mut ns = Vec::<Node>::new();
+    loop {
+      match self.next_token() {
+        Tok::CloseParen(_) if close == ')' => return Ok(ns),
+        Tok::CloseBracket(_) if close == ']' => return Ok(ns),
+        Tok::CloseBrace(_) if close == '}' => return Ok(ns),
+        Tok::CloseParen(ref at) | Tok::CloseBracket(ref at) | Tok::CloseBrace(ref at) => {
+          return Err(syntax_error(at, &format!("Expected '{}' to close line {}", close, loc.line)))
+        }
+        Tok::Eof(_) => {
+          return Err(syntax_error(
+            loc,
+            &format!("Unbalanced braces starting at line: {:?}", loc.line),
+          ))
+        }
+        tok => ns.push(self.expr_from(tok)?),
+      }
+    }
   }
 
-  pub fn next_progn(&mut self) -> Node {
+  pub fn next_progn(&mut self) -> Result<Node, HarpError> {
     let mut ns = Vec::<Node>::new();
 
-    while !self.at_eof() {
-      let expr = self.next_expr();
-      match expr {
-        Node::Unit(_) => {
-          break;
-        }
-        _ => ns.push(expr),
+    loop {
+      match self.next_expr()? {
+        Node::Unit(_) => break,
+        expr => ns.push(expr),
       }
     }
 
-    Node::Progn(ns, NodeInfo::new())
+    Ok(Node::Progn(ns, NodeInfo::new()))
   }
 }
@@ -1 +1,114 @@
+pub mod optimizer;
+#[allow(clippy::module_inception)]
 pub mod translator;
+
+#[cfg(test)]
+use crate::evaluator::opcodes::Opcode;
+#[cfg(test)]
+use crate::evaluator::script::Script;
+#[cfg(test)]
+use crate::evaluator::value::Value;
+#[cfg(test)]
+use crate::reader::reader::Reader;
+#[cfg(test)]
+use optimizer::{optimize_script, OptLevel};
+#[cfg(test)]
+use translator::Translator;
+
+#[cfg(test)]
+fn listing(script: &Script) -> Vec<String> {
+  script.instructions.iter().map(|op| op.to_string()).collect()
+}
+
+#[cfg(test)]
+fn translate(code: &str, opt_level: OptLevel) -> Script {
+  Translator::with_opt_level(opt_level).progn_to_script(Reader::new(code).next_progn().unwrap())
+}
+
+#[test]
+fn optimizer_constant_folding_test() {
+  let script = translate("(+ 1 (* 2 3) (- 10 4))", OptLevel::O2);
+  assert_eq!(listing(&script), vec!["Const(0)"]);
+  assert_eq!(script.constants, vec![Value::Number(13.0)]);
+
+  // Calls with non literal arguments are left alone
+  let script = translate("(+ x 1)", OptLevel::O2);
+  assert_eq!(listing(&script).last().unwrap(), "Call(#args: 2)");
+}
+
+#[test]
+fn optimizer_generic_methods_test() {
+  // A defmethod later in the program keeps the call
+  let script = translate("(+ 2 5) (defmethod + ((a Number) (b Number)) (* a b))", OptLevel::O2);
+  assert_eq!(listing(&script)[3], "Call(#args: 2)");
+  assert!(!script.constants.contains(&Value::Number(7.0)));
+
+  // As do methods already in the environment
+  let mut env = crate::common::prelude::make_std_env();
+  let code = "(defmethod * ((a Number) (b Number)) (+ a b))";
+  crate::evaluator::quick_eval::qeval_progn(&Reader::new(code).next_progn().unwrap(), &mut env).unwrap();
+  let translate_in_env = |code| {
+    Translator::for_env(OptLevel::O2, &env).progn_to_script(Reader::new(code).next_progn().unwrap())
+  };
+  assert_eq!(listing(&translate_in_env("(* 2 5)")).last().unwrap(), "Call(#args: 2)");
+  assert_eq!(translate_in_env("(- 7 5)").constants, vec![Value::Number(2.0)]);
+}
+
+#[test]
+fn optimizer_dead_branch_test() {
+  let script = translate("(if (eq 1 1) \"yes\" (print \"no\"))", OptLevel::O2);
+  assert_eq!(listing(&script), vec!["Const(0)"]);
+  assert_eq!(script.constants, vec![Value::String("yes".to_string())]);
+
+  let script = translate("(if #f 1)", OptLevel::O2);
+  assert_eq!(listing(&script), vec!["Push(())"]);
+}
+
+#[test]
+fn optimizer_peephole_test() {
+  let script = translate("1 \"two\" #t x", OptLevel::O1);
+  assert_eq!(listing(&script), vec!["Load(#const: 2)"]);
+
+  // Without optimizations every intermediate value is pushed and popped
+  let script = translate("1 \"two\" #t x", OptLevel::O0);
+  assert_eq!(script.instructions.len(), 7);
+}
+
+#[test]
+fn optimizer_jump_threading_test() {
+  let mut script = Script::new();
+  script.new_inst(Opcode::Push(Value::Bool(true)));
+  script.new_inst(Opcode::JumpIfFalse(3));
+  script.new_inst(Opcode::Push(Value::Unit));
+  script.new_inst(Opcode::Jump(5));
+  script.new_inst(Opcode::Push(Value::Unit));
+  script.new_inst(Opcode::Jump(6));
+  optimize_script(&mut script);
+
+  assert_eq!(
+    listing(&script),
+    vec!["Push(#t)", "JumpIfFalse(#addr: 5)", "Push(())", "Jump(#addr: 5)", "Push(())"]
+  );
+}
+
+#[test]
+fn constant_pool_test() {
+  let script = translate("(+ 1 1 1) (print \"a\" \"a\") 1", OptLevel::O0);
+  assert_eq!(script.constants.len(), 4);
+  assert_eq!(script.contains_const(&Value::Number(1.0)), Some(0));
+  assert_eq!(script.contains_const(&Value::Atom("print".to_string())), Some(3));
+}
+
+#[test]
+fn match_jump_table_test() {
+  let script = translate("(match k :up 1 :down 2 _ 3)", OptLevel::O1);
+  let ops = listing(&script);
+  assert_eq!(ops[1], "JumpTable(#cases: 2, #default: 12)");
+  assert_eq!(ops[12], "Pop");
+  // Each clause runs in a scope of its own
+  assert_eq!(ops[2..6], ["Pop", "PushScope", "Const(1)", "PopScope"]);
+
+  // Guards and binding patterns go through the match special form
+  let script = translate("(match k :up 1 x :when (f x) 2)", OptLevel::O1);
+  assert_eq!(listing(&script).last().unwrap(), "CallSpecial(#args: 7)");
+}
@@ -0,0 +1,289 @@
+/*
+  Optimization passes run by the translator. The ast passes (constant folding, dead branches) work on values
+  before translation, the peephole passes (push/pop pairs, jump threading) clean up the emitted script.
+  Folding assumes the pure prelude functions below have not been rebound with set!. The generic ones (+, -, *, /
+  and eq) are not folded once they have methods, in the environment or anywhere in the program being translated,
+  and no function is folded where the program binds its name locally.
+*/
+
+use std::collections::HashSet;
+
+use crate::common::prelude::make_std_env;
+use crate::evaluator::matching::parse_clauses;
+use crate::evaluator::opcodes::Opcode;
+use crate::evaluator::script::Script;
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+  // Translate as is
+  #[default]
+  O0,
+  // Peephole passes over the script
+  O1,
+  // Constant folding and dead branch elimination on top of O1
+  O2,
+}
+
+impl OptLevel {
+  pub fn from_flag(flag: &str) -> Option<OptLevel> {
+    match flag {
+      "-O0" => Some(OptLevel::O0),
+      "-O1" => Some(OptLevel::O1),
+      "-O2" => Some(OptLevel::O2),
+      _ => None,
+    }
+  }
+}
+
+fn is_literal(value: &Value) -> bool {
+  matches!(value, Value::Number(_) | Value::String(_) | Value::Bool(_))
+}
+
+fn is_foldable_call(name: &str, args: &[Value]) -> bool {
+  let numbers = || args.iter().all(|a| matches!(a, Value::Number(_)));
+  match name {
+    "+" | "-" | "*" | "/" | "mod" | "<" | ">" | "<=" | ">=" => numbers(),
+    "eq" | "not" => !args.is_empty() && args.iter().all(is_literal),
+    _ => false,
+  }
+}
+
+// The names in the syntax of a destructuring pattern or parameter list, defaults included as any name there could
+// be one bound
+fn pattern_names(pattern: &Value, names: &mut HashSet<String>) {
+  match pattern {
+    Value::Atom(name) if !is_keyword(name) && !name.starts_with('&') && name != "_" => {
+      names.insert(name.clone());
+    }
+    Value::List(xs) => xs.iter().for_each(|x| pattern_names(x, names)),
+    Value::Vector(xs) => xs.iter().for_each(|x| pattern_names(x, names)),
+    Value::Map(entries) => entries.iter().for_each(|(key, x)| {
+      pattern_names(key, names);
+      pattern_names(x, names);
+    }),
+    _ => {}
+  }
+}
+
+// Adds the names calls to which can't be folded: functions value gives methods to with defmethod, and every name
+// it binds with defun, lambda, let, doseq, match or catch. Scope is dynamic, a local binding shadows the prelude's
+// function in every call made while it's in scope, not only in the body that binds it
+pub fn unfoldable_names(value: &Value, names: &mut HashSet<String>) {
+  let xs = match value {
+    Value::List(xs) => xs.to_vec(),
+    Value::Do(xs) => xs.clone(),
+    _ => return,
+  };
+  match &xs[..] {
+    [Value::Atom(head), Value::Atom(name), ..] if head == "defmethod" => {
+      names.insert(name.clone());
+    }
+    [Value::Atom(head), name, params, ..] if head == "defun" => {
+      pattern_names(name, names);
+      pattern_names(params, names);
+    }
+    [Value::Atom(head), params, ..] if head == "lambda" || head == "λ" => pattern_names(params, names),
+    [Value::Atom(head), Value::Vector(bindings), ..] if head == "let" || head == "doseq" => {
+      bindings.iter().step_by(2).for_each(|pattern| pattern_names(pattern, names))
+    }
+    [Value::Atom(head), _, clauses @ ..] if head == "match" => {
+      let mut bound = Vec::new();
+      for clause in parse_clauses(clauses).unwrap_or_default() {
+        clause.pattern.bound_names(&mut bound);
+      }
+      names.extend(bound);
+    }
+    [Value::Atom(catch), name, ..] if catch == "catch" => pattern_names(name, names),
+    _ => {}
+  }
+  for x in &xs {
+    unfoldable_names(x, names);
+  }
+}
+
+// A constant folding pass over an expression, the prelude calls are folded with is made once for the whole pass
+struct Folder<'a> {
+  unfoldable: &'a HashSet<String>,
+  prelude: Option<EnvHead>,
+}
+
+// Constant folds calls to pure prelude functions and removes branches of ifs with literal conditions, calls to the
+// names in unfoldable are left as they are, see unfoldable_names
+pub fn fold_value(value: &Value, unfoldable: &HashSet<String>) -> Value {
+  Folder { unfoldable, prelude: None }.fold(value)
+}
+
+impl Folder<'_> {
+  fn fold_all(&mut self, xs: &[Value]) -> Vec<Value> {
+    xs.iter().map(|x| self.fold(x)).collect()
+  }
+
+  fn fold(&mut self, value: &Value) -> Value {
+    let xs = match value {
+      Value::List(xs) => xs.to_vec(),
+      Value::Do(xs) => return Value::Do(self.fold_all(xs)),
+      _ => return value.clone(),
+    };
+
+    match &xs[..] {
+      // Patterns are syntax, not expressions
+      [Value::Atom(head), ..] if head == "quote" || head == "match" => value.clone(),
+
+      // Bodies are folded when the function is called, in the scope of the call which may shadow prelude functions
+      [Value::Atom(head), ..] if head == "lambda" || head == "λ" || head == "defun" => value.clone(),
+
+      [Value::Atom(head), name, expr] if head == "def" || head == "set!" => {
+        Value::list(vec![xs[0].clone(), name.clone(), self.fold(expr)])
+      }
+
+      [Value::Atom(head), cond, rest @ ..] if head == "if" && (rest.len() == 1 || rest.len() == 2) => {
+        match self.fold(cond) {
+          Value::Bool(true) => self.fold(&rest[0]),
+          Value::Bool(false) => match rest.get(1) {
+            Some(alternative) => self.fold(alternative),
+            None => Value::Unit,
+          },
+          cond => {
+            let mut res = vec![xs[0].clone(), cond];
+            res.extend(self.fold_all(rest));
+            Value::list(res)
+          }
+        }
+      }
+
+      [Value::Atom(head), args @ ..] => {
+        let args = self.fold_all(args);
+        if let Some(value) = self.call(head, &args) {
+          return value;
+        }
+        let mut res = vec![xs[0].clone()];
+        res.extend(args);
+        Value::list(res)
+      }
+
+      _ => Value::list(self.fold_all(&xs)),
+    }
+  }
+
+  // The value of a call to a pure prelude function, when it can be worked out now
+  fn call(&mut self, name: &str, args: &[Value]) -> Option<Value> {
+    if self.unfoldable.contains(name) || !is_foldable_call(name, args) {
+      return None;
+    }
+    let env = self.prelude.get_or_insert_with(make_std_env);
+    match env.get(name.to_string()) {
+      Some(Value::NativeFunc(native)) => native.call(args.to_vec(), env).ok(),
+      _ => None,
+    }
+  }
+}
+
+fn jump_targets(op: &Opcode) -> Vec<usize> {
+  match op {
+    Opcode::Jump(addr) | Opcode::JumpIfFalse(addr) | Opcode::Try(addr) => vec![*addr],
+    Opcode::JumpTable(table) => table.targets().collect(),
+    _ => Vec::new(),
+  }
+}
+
+// Drops the marked instructions, retargeting jumps to the next instruction that is kept
+fn remove_instructions(script: &mut Script, remove: &[bool]) {
+  let mut new_addr = Vec::with_capacity(remove.len() + 1);
+  let mut kept = 0;
+  for removed in remove {
+    new_addr.push(kept);
+    if !removed {
+      kept += 1;
+    }
+  }
+  new_addr.push(kept);
+
+  let instructions = std::mem::take(&mut script.instructions);
+  for (op, removed) in instructions.into_iter().zip(remove) {
+    if *removed {
+      continue;
+    }
+    script.instructions.push(match op {
+      Opcode::Jump(addr) => Opcode::Jump(new_addr[addr]),
+      Opcode::JumpIfFalse(addr) => Opcode::JumpIfFalse(new_addr[addr]),
+      Opcode::Try(addr) => Opcode::Try(new_addr[addr]),
+      Opcode::JumpTable(table) => Opcode::JumpTable(Box::new(table.map_targets(|addr| new_addr[addr]))),
+      op => op,
+    });
+  }
+}
+
+// Removes values pushed only to be popped, and jumps to the very next instruction
+pub fn peephole(script: &mut Script) -> bool {
+  let len = script.instructions.len();
+  let mut targeted = vec![false; len + 1];
+  for op in script.instructions.iter() {
+    for addr in jump_targets(op) {
+      targeted[addr] = true;
+    }
+  }
+
+  let mut remove = vec![false; len];
+  let mut i = 0;
+  while i < len {
+    match (&script.instructions[i], script.instructions.get(i + 1)) {
+      (Opcode::Push(_), Some(Opcode::Pop)) | (Opcode::Const(_), Some(Opcode::Pop)) if !targeted[i + 1] => {
+        remove[i] = true;
+        remove[i + 1] = true;
+        i += 2;
+      }
+      (Opcode::Jump(addr), _) if *addr == i + 1 => {
+        remove[i] = true;
+        i += 1;
+      }
+      _ => i += 1,
+    }
+  }
+
+  let changed = remove.iter().any(|r| *r);
+  if changed {
+    remove_instructions(script, &remove);
+  }
+  changed
+}
+
+// Points jumps that land on an unconditional jump straight at its final destination
+pub fn thread_jumps(script: &mut Script) -> bool {
+  let mut changed = false;
+  for i in 0..script.instructions.len() {
+    let start = match &script.instructions[i] {
+      Opcode::Jump(addr) | Opcode::JumpIfFalse(addr) => *addr,
+      _ => continue,
+    };
+
+    let mut addr = start;
+    let mut hops = 0;
+    while let Some(Opcode::Jump(next)) = script.instructions.get(addr) {
+      if *next == addr || hops > script.instructions.len() {
+        break;
+      }
+      addr = *next;
+      hops += 1;
+    }
+
+    if addr != start {
+      changed = true;
+      match &mut script.instructions[i] {
+        Opcode::Jump(target) | Opcode::JumpIfFalse(target) => *target = addr,
+        _ => {}
+      }
+    }
+  }
+  changed
+}
+
+pub fn optimize_script(script: &mut Script) {
+  loop {
+    let threaded = thread_jumps(script);
+    let peeped = peephole(script);
+    if !threaded && !peeped {
+      break;
+    }
+  }
+}
@@ -1,78 +1,364 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::common::prelude::{is_special_form, std_defun, std_lambda};
+use crate::evaluator::generic::{with_methods, Generics};
+use crate::evaluator::matching::{no_match, parse_clauses, Clause, MatchPattern};
+use crate::evaluator::native::{Arity, Native};
 use crate::evaluator::opcodes::Opcode;
-use crate::evaluator::script::Script;
-use crate::evaluator::value::Value;
-use crate::reader::ast::Node;
+use crate::evaluator::script::{JumpTable, Script};
+use crate::evaluator::value::{is_keyword, EnvHead, Value};
+use crate::reader::ast::{to_value, Node};
+use crate::translator::optimizer::{fold_value, optimize_script, unfoldable_names, OptLevel};
+
+// Splits clauses into literal cases and a trailing wildcard, when none has a guard
+fn table_clauses(clauses: &[Clause]) -> Option<(&[Clause], Option<&Value>)> {
+  let (cases, default) = match clauses.split_last() {
+    Some((last, cases)) if matches!(last.pattern, MatchPattern::Wildcard) => (cases, Some(&last.body)),
+    _ => (clauses, None),
+  };
+  let literal = |clause: &Clause| clause.guard.is_none() && clause.pattern.literal().is_some_and(JumpTable::can_hold);
+  if !cases.is_empty() && cases.iter().all(literal) && clauses.iter().all(|c| c.guard.is_none()) {
+    Some((cases, default))
+  } else {
+    None
+  }
+}
 
+#[derive(Default)]
 pub struct Translator {
   script: Script,
+  opt_level: OptLevel,
+  forms: Option<Rc<RefCell<HashSet<String>>>>,
+  generics: Option<Generics>,
+  // Names the program being translated adds methods to or binds locally, see unfoldable_names
+  unfoldable: HashSet<String>,
 }
 
 impl Translator {
   pub fn new() -> Translator {
+    Translator::with_opt_level(OptLevel::O0)
+  }
+
+  pub fn with_opt_level(opt_level: OptLevel) -> Translator {
     Translator {
       script: Script::new(),
+      opt_level,
+      forms: None,
+      generics: None,
+      unfoldable: HashSet::new(),
+    }
+  }
+
+  // Uses the special forms and generic functions of the environment, instead of the standard ones. Function bodies
+  // are translated when called, in a scope whose local bindings shadow prelude functions just as the program's do
+  pub fn for_env(opt_level: OptLevel, env: &EnvHead) -> Translator {
+    Translator {
+      forms: Some(env.special_forms()),
+      generics: Some(env.generics()),
+      unfoldable: env.local_names(),
+      ..Translator::with_opt_level(opt_level)
+    }
+  }
+
+  fn is_special_form(&self, name: &str) -> bool {
+    match &self.forms {
+      Some(forms) => forms.borrow().contains(name),
+      None => is_special_form(name),
+    }
+  }
+
+  fn finish(&mut self) -> Script {
+    if self.opt_level >= OptLevel::O1 {
+      optimize_script(&mut self.script);
     }
+    self.script.clone()
   }
 
-  pub fn handle_const(&mut self, value: &Value) -> () {
-    return match self.script.contains_const(&value) {
+  pub fn handle_const(&mut self, value: &Value) {
+    match self.script.contains_const(value) {
       Some(index) => self.script.new_inst(Opcode::Const(index)),
       _ => {
-        let index = self.script.new_const(&value);
+        let index = self.script.new_const(value);
         self.script.new_inst(Opcode::Const(index))
       }
-    };
+    }
+  }
+
+  fn const_index(&mut self, value: &Value) -> usize {
+    match self.script.contains_const(value) {
+      Some(index) => index,
+      _ => self.script.new_const(value),
+    }
+  }
+
+  pub fn transpile_if_expr(&mut self, expr: &Value, consequent: &Value, alternative: Option<&Value>) {
+    self.translate_value(expr);
+
+    let to_alternative = self.script.next_addr();
+    self.script.new_inst(Opcode::JumpIfFalse(0));
+
+    self.translate_value(consequent);
+
+    let to_end = self.script.next_addr();
+    self.script.new_inst(Opcode::Jump(0));
+
+    let alternative_addr = self.script.next_addr();
+    self.script.patch_jump(to_alternative, alternative_addr);
+
+    match alternative {
+      Some(alternative) => self.translate_value(alternative),
+      None => self.script.new_inst(Opcode::Push(Value::Unit)),
+    }
+
+    let end_addr = self.script.next_addr();
+    self.script.patch_jump(to_end, end_addr);
   }
 
-  pub fn transpile_if_expr(&mut self, expr: &Node, consequent: &Node, alternative: Option<&Node>) {
-    // let start = self.script.instructions.len() - 1;
+  // The handler runs as a one parameter function applied to the error message
+  pub fn transpile_try_expr(&mut self, expr: &Value, name: &Value, handler: &[Value]) {
+    let to_handler = self.script.next_addr();
+    self.script.new_inst(Opcode::Try(0));
+
+    self.translate_value(expr);
+    self.script.new_inst(Opcode::EndTry);
+
+    let to_end = self.script.next_addr();
+    self.script.new_inst(Opcode::Jump(0));
+
+    let handler_addr = self.script.next_addr();
+    self.script.patch_jump(to_handler, handler_addr);
 
-    self.translate_expr(expr);
+    let mut lambda = vec![Value::list(vec![name.clone()])];
+    lambda.extend(handler.iter().cloned());
+    match std_lambda(lambda, &mut EnvHead::new()) {
+      Ok(func) => self.script.new_inst(Opcode::Push(func)),
+      Err(err) => panic!("Can't translate catch clause: {}", err),
+    }
+    self.script.new_inst(Opcode::Call(1));
+
+    let end_addr = self.script.next_addr();
+    self.script.patch_jump(to_end, end_addr);
+  }
 
-    // self.translate_expr(&consequent);
+  // A clause runs in a scope of its own, so what it defines is gone after the match as with the match form
+  fn translate_clause_body(&mut self, body: &Value) {
+    self.script.new_inst(Opcode::PushScope);
+    self.translate_value(body);
+    self.script.new_inst(Opcode::PopScope);
   }
 
-  pub fn translate_list(&mut self, list: &Vec<Node>) -> () {
+  // Literal patterns dispatch through a jump table, the matched value stays on the stack until a clause runs
+  fn transpile_match_table(&mut self, expr: &Value, cases: &[Clause], default: Option<&Value>) {
+    self.translate_value(expr);
+    let at = self.script.next_addr();
+    self.script.new_inst(Opcode::JumpTable(Box::new(JumpTable::new())));
+
+    let mut table = JumpTable::new();
+    let mut to_end = Vec::new();
+    for clause in cases {
+      if let Some(literal) = clause.pattern.literal() {
+        table.insert(literal, self.script.next_addr());
+      }
+      self.script.new_inst(Opcode::Pop);
+      self.translate_clause_body(&clause.body);
+      to_end.push(self.script.next_addr());
+      self.script.new_inst(Opcode::Jump(0));
+    }
+
+    table.default = self.script.next_addr();
+    match default {
+      Some(body) => {
+        self.script.new_inst(Opcode::Pop);
+        self.translate_clause_body(body);
+      }
+      None => {
+        let fail = Native::new("match", Arity::exact(1), |args, _env| Err(no_match(&args[0])));
+        self.script.new_inst(Opcode::Push(Value::NativeFunc(fail)));
+        self.script.new_inst(Opcode::Call(1));
+      }
+    }
+
+    let end_addr = self.script.next_addr();
+    for at in to_end {
+      self.script.patch_jump(at, end_addr);
+    }
+    self.script.instructions[at] = Opcode::JumpTable(Box::new(table));
+  }
+
+  pub fn translate_list(&mut self, list: &[Value]) {
     // Handle special forms, (until macros)
-    match &list[..] {
-      [Node::AtomLit(lexeme, _), expr, consequent] if lexeme == "if" => {
-        self.transpile_if_expr(expr, consequent, None);
+    match list {
+      [] => panic!("Cannot translate a call to an empty list"),
+      [Value::Atom(lexeme), expr, consequent] if lexeme == "if" => {
+        return self.transpile_if_expr(expr, consequent, None);
+      }
+      [Value::Atom(lexeme), expr, consequent, alternative] if lexeme == "if" => {
+        return self.transpile_if_expr(expr, consequent, Some(alternative));
+      }
+      [Value::Atom(lexeme), value] if lexeme == "quote" => {
+        return self.script.new_inst(Opcode::Push(value.clone()));
+      }
+      [Value::Atom(lexeme), name @ Value::Atom(_), value] if lexeme == "def" => {
+        self.translate_value(value);
+        let index = self.const_index(name);
+        return self.script.new_inst(Opcode::Define(index));
+      }
+      [Value::Atom(lexeme), name @ Value::Atom(_), value] if lexeme == "set!" => {
+        self.translate_value(value);
+        let index = self.const_index(name);
+        return self.script.new_inst(Opcode::Set(index));
+      }
+      [Value::Atom(lexeme), args @ ..] if lexeme == "lambda" || lexeme == "λ" => {
+        // Functions are built ahead of time, their bodies are translated when called.
+        // Malformed ones fall through to a regular call which raises the error at runtime
+        if let Ok(func) = std_lambda(args.to_vec(), &mut EnvHead::new()) {
+          return self.script.new_inst(Opcode::Push(func));
+        }
+      }
+      [Value::Atom(lexeme), args @ ..] if lexeme == "defun" => {
+        if let Ok(func) = std_defun(args.to_vec(), &mut EnvHead::new()) {
+          self.script.new_inst(Opcode::Push(func));
+          let index = self.const_index(&args[0]);
+          return self.script.new_inst(Opcode::Set(index));
+        }
       }
-      [Node::AtomLit(lexeme, _), expr, consequent, alternative] if lexeme == "if" => {
-        self.transpile_if_expr(expr, consequent, Some(alternative));
+      [Value::Atom(lexeme), expr, Value::List(clause)] if lexeme == "try" => {
+        if let [Value::Atom(catch), name @ Value::Atom(_), handler @ ..] = &clause.to_vec()[..] {
+          if catch == "catch" {
+            return self.transpile_try_expr(expr, name, handler);
+          }
+        }
+      }
+      [Value::Atom(lexeme), expr, clauses @ ..] if lexeme == "match" => {
+        if let Ok(clauses) = parse_clauses(clauses) {
+          if let Some((cases, default)) = table_clauses(&clauses) {
+            return self.transpile_match_table(expr, cases, default);
+          }
+        }
       }
       _ => {}
     }
 
-    for node in list.iter().rev() {
-      self.translate_expr(node);
+    // Other special forms, and malformed ones from above, get their arguments as syntax
+    if let [head @ Value::Atom(lexeme), args @ ..] = list {
+      if self.is_special_form(lexeme) {
+        for value in args {
+          self.script.new_inst(Opcode::Push(value.clone()));
+        }
+        self.translate_value(head);
+        return self.script.new_inst(Opcode::CallSpecial(args.len()));
+      }
     }
 
+    // Arguments are evaluated left to right, the callee ends up on top
+    for value in &list[1..] {
+      self.translate_value(value);
+    }
+    self.translate_value(&list[0]);
+
     self.script.new_inst(Opcode::Call(list.len() - 1))
   }
 
-  pub fn translate_expr(&mut self, ns: &Node) -> () {
-    match ns {
-      Node::NumberLit(number, _) => self.handle_const(&Value::Number(number.clone())),
-      Node::StringLit(lexeme, _) => self.handle_const(&Value::String(lexeme.clone())),
-      Node::AtomLit(lexeme, _) => self.handle_const(&Value::Atom(lexeme.clone())),
-      Node::BoolLit(value, _) => self.script.new_inst(Opcode::Push(Value::Bool(*value))),
-      Node::List(xs, _) => self.translate_list(xs),
+  pub fn translate_progn(&mut self, xs: &[Value]) {
+    if xs.is_empty() {
+      return self.script.new_inst(Opcode::Push(Value::Unit));
+    }
 
+    for (i, value) in xs.iter().enumerate() {
+      if i > 0 {
+        self.script.new_inst(Opcode::Pop);
+      }
+      self.translate_value(value);
+    }
+  }
+
+  pub fn translate_value(&mut self, value: &Value) {
+    match value {
+      Value::Number(_) | Value::String(_) => self.handle_const(value),
+      Value::Atom(name) if is_keyword(name) => self.handle_const(value),
+      Value::Atom(_) => {
+        let index = self.const_index(value);
+        self.script.new_inst(Opcode::Load(index))
+      }
+      Value::Bool(_)
+      | Value::Unit
+      | Value::NativeFunc(_)
+      | Value::Func(_, _, _)
+      | Value::UserData(_)
+      | Value::Record(_)
+      | Value::Variant(_)
+      | Value::Lazy(_)
+      | Value::Regex(_) => {
+        self.script.new_inst(Opcode::Push(value.clone()))
+      }
+      Value::List(xs) => self.translate_list(&xs.to_vec()),
+      Value::Vector(xs) => {
+        for x in xs {
+          self.translate_value(x);
+        }
+        self.script.new_inst(Opcode::Vector(xs.len()))
+      }
+      Value::Map(entries) => {
+        for (key, value) in entries {
+          self.translate_value(key);
+          self.translate_value(value);
+        }
+        self.script.new_inst(Opcode::Map(entries.len()))
+      }
+      Value::Do(xs) => self.translate_progn(xs),
+    }
+  }
+
+  pub fn translate_expr(&mut self, ns: &Node) {
+    match ns {
       Node::Unit(_) => {}
-      _ => panic!("Can't translate expr node: {:?}", ns),
+      Node::Progn(_, _) => panic!("Can't translate expr node: {:?}", ns),
+      _ => self.translate_top(&to_value(ns)),
+    }
+  }
+
+  // Entry point for whole expressions, where the ast level passes apply
+  fn translate_top(&mut self, value: &Value) {
+    if self.opt_level >= OptLevel::O2 {
+      let mut unfoldable = self.unfoldable.clone();
+      if let Some(generics) = &self.generics {
+        unfoldable.extend(with_methods(generics));
+      }
+      unfoldable_names(value, &mut unfoldable);
+      self.translate_value(&fold_value(value, &unfoldable))
+    } else {
+      self.translate_value(value)
     }
   }
 
   pub fn progn_to_script(&mut self, node: Node) -> Script {
     match node {
       Node::Progn(xs, _) => {
-        for sub in xs {
-          self.translate_expr(&sub)
+        if xs.is_empty() {
+          self.script.new_inst(Opcode::Push(Value::Unit));
+        }
+        // A defmethod or local binding anywhere in the program applies to the calls before it too
+        if self.opt_level >= OptLevel::O2 {
+          for sub in xs.iter() {
+            unfoldable_names(&to_value(sub), &mut self.unfoldable);
+          }
+        }
+        for (i, sub) in xs.iter().enumerate() {
+          if i > 0 {
+            self.script.new_inst(Opcode::Pop);
+          }
+          self.translate_expr(sub)
         }
       }
       otherwise => panic!("Progn to script expects a progn, but got: {:?}", otherwise),
     }
-    self.script.clone()
+    self.finish()
+  }
+
+  pub fn value_to_script(&mut self, value: &Value) -> Script {
+    self.translate_top(value);
+    self.finish()
   }
 }
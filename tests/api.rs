@@ -0,0 +1,236 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use harp::{
+  make_std_env, make_std_env_with_output, qeval_progn, Arity, HarpError, Interpreter, LazySeq, Limits, Native,
+  Reader, UserData, UserType, Value,
+};
+
+fn eval(code: &str) -> Result<Value, HarpError> {
+  let mut env = make_std_env();
+  qeval_progn(&Reader::new(code).next_progn()?, &mut env)
+}
+
+#[test]
+fn embed_eval_test() {
+  assert_eq!(eval("(defun sq (x) (* x x)) (sq 7)"), Ok(Value::Number(49.0)));
+  assert!(matches!(eval("(+ 1 \"a\")"), Err(HarpError::Runtime(_))));
+}
+
+#[test]
+fn embed_output_and_limits_test() {
+  let out = Rc::new(RefCell::new(Vec::new()));
+  let mut env = make_std_env_with_output(out.clone());
+  env.budget().set_limits(Limits {
+    max_steps: Some(50),
+    ..Limits::unlimited()
+  });
+
+  let progn = Reader::new("(print \"hello\")").next_progn().unwrap();
+  assert!(matches!(qeval_progn(&progn, &mut env), Ok(Value::Unit)));
+  assert_eq!(String::from_utf8_lossy(&out.borrow()), "hello\n");
+
+  let progn = Reader::new("(defun f (n) (f n)) (f 1)").next_progn().unwrap();
+  env.budget().reset();
+  assert!(matches!(
+    qeval_progn(&progn, &mut env),
+    Err(HarpError::LimitExceeded(_))
+  ));
+}
+
+fn buffer() -> Rc<RefCell<Vec<u8>>> {
+  Rc::new(RefCell::new(Vec::new()))
+}
+
+fn text(buf: &Rc<RefCell<Vec<u8>>>) -> String {
+  String::from_utf8_lossy(&buf.borrow()).to_string()
+}
+
+#[test]
+fn interpreter_eval_and_call_test() {
+  let mut interp = Interpreter::new();
+  assert_eq!(
+    interp.eval_str("(defun greet (name) name) (+ 1 2)"),
+    Ok(Value::Number(3.0))
+  );
+
+  // Arguments are values, they are not evaluated again
+  assert_eq!(
+    interp.call("greet", vec![Value::Atom("not-a-variable".to_string())]),
+    Ok(Value::Atom("not-a-variable".to_string()))
+  );
+  assert_eq!(
+    interp.call("+", vec![Value::Number(2.0), Value::Number(5.0)]),
+    Ok(Value::Number(7.0))
+  );
+  assert!(matches!(interp.call("nope", vec![]), Err(HarpError::Runtime(_))));
+}
+
+#[test]
+fn interpreter_globals_test() {
+  let mut interp = Interpreter::new();
+  interp.set_global("lives", Value::Number(3.0));
+  assert_eq!(interp.eval_str("(set! lives (- lives 1))"), Ok(Value::Number(2.0)));
+  assert_eq!(interp.get_global("lives"), Some(Value::Number(2.0)));
+  assert_eq!(interp.get_global("missing"), None);
+}
+
+#[test]
+fn interpreter_sinks_test() {
+  let (out, err) = (buffer(), buffer());
+  let mut interp = Interpreter::with_outputs(out.clone(), err.clone());
+
+  interp.eval_str("(print \"to stdout\") undefined-thing").unwrap();
+  assert_eq!(text(&out), "to stdout\n");
+  assert_eq!(text(&err), "Undefind Variable undefined-thing\n");
+}
+
+#[test]
+fn interpreter_errors_test() {
+  let mut interp = Interpreter::new();
+  assert!(matches!(interp.eval_str("(print 1"), Err(HarpError::Syntax(_))));
+  assert!(matches!(
+    interp.eval_file("does/not/exist.harp"),
+    Err(HarpError::Runtime(_))
+  ));
+
+  interp.set_limits(Limits {
+    max_depth: Some(10),
+    ..Limits::unlimited()
+  });
+  assert!(matches!(
+    interp.eval_str("(defun f () (f)) (f)"),
+    Err(HarpError::LimitExceeded(_))
+  ));
+}
+
+#[test]
+fn register_closure_test() {
+  let mut interp = Interpreter::new();
+  let calls = Rc::new(RefCell::new(0));
+  let counter = calls.clone();
+  interp.register("add", move |a: f64, b: f64| {
+    *counter.borrow_mut() += 1;
+    a + b
+  });
+  interp.register("greet", |name: String| format!("hi {}", name));
+
+  assert_eq!(interp.eval_str("(add 1 (add 2 3))"), Ok(Value::Number(6.0)));
+  assert_eq!(*calls.borrow(), 2);
+  assert_eq!(interp.eval_str("(greet \"bob\")"), Ok(Value::String("hi bob".to_string())));
+  assert_eq!(interp.call("add", vec![Value::Number(2.0), Value::Number(5.0)]), Ok(Value::Number(7.0)));
+
+  assert_eq!(
+    interp.eval_str("(add 1 \"x\")"),
+    Err(HarpError::Runtime("add argument 2 expected a number, but got x".to_string()))
+  );
+  assert_eq!(
+    interp.eval_str("(add 1)"),
+    Err(HarpError::Runtime("add expected 2 arguments, but got 1".to_string()))
+  );
+}
+
+#[test]
+fn register_native_test() {
+  let mut interp = Interpreter::new();
+  interp.env().set(
+    "count".to_string(),
+    Value::NativeFunc(Native::new("count", Arity::any(), |args, _env| {
+      Ok(Value::Number(args.len() as f64))
+    })),
+  );
+  assert_eq!(interp.eval_str("(count 1 2 3)"), Ok(Value::Number(3.0)));
+  assert_eq!(
+    interp.eval_str("(if #t)"),
+    Err(HarpError::Runtime("if expected 2 to 3 arguments, but got 1".to_string()))
+  );
+}
+
+#[derive(PartialEq)]
+struct Player {
+  x: f64,
+}
+
+#[test]
+fn userdata_methods_test() {
+  let mut interp = Interpreter::new();
+  let player = interp.env().register_type(
+    UserType::new("player")
+      .with_display(|p: &Player| format!("#<player at {}>", p.x))
+      .with_eq::<Player>()
+      .with_method("move", |p: UserData, dx: f64| -> Result<f64, HarpError> {
+        let mut p = p.borrow_mut::<Player>()?;
+        p.x += dx;
+        Ok(p.x)
+      })
+      .with_method("x", |p: UserData| p.borrow::<Player>().map(|p| p.x)),
+  );
+  let p = UserData::new(&player, Player { x: 1.0 });
+  interp.set_global("p", Value::UserData(p.clone()));
+
+  assert_eq!(interp.eval_str("(player.move p 3) (player.x p)"), Ok(Value::Number(4.0)));
+  assert_eq!(p.borrow::<Player>().unwrap().x, 4.0);
+  assert_eq!(interp.eval_str("p").unwrap().to_string(), "#<player at 4>");
+  assert!(p.borrow::<String>().is_err());
+
+  interp.set_global("q", Value::UserData(UserData::new(&player, Player { x: 4.0 })));
+  assert_eq!(interp.eval_str("(eq p q)"), Ok(Value::Bool(true)));
+  assert_eq!(interp.eval_str("(eq? p q)"), Ok(Value::Bool(false)));
+  assert_eq!(interp.eval_str("(eq? [p] [p])"), Ok(Value::Bool(true)));
+  assert_eq!(
+    interp.eval_str("(player.move 5 1)"),
+    Err(HarpError::Runtime("player.move expected a player receiver, but got 5".to_string()))
+  );
+}
+
+#[test]
+fn userdata_identity_test() {
+  let mut interp = Interpreter::new();
+  let door = interp.env().register_type(UserType::new("door"));
+  interp.set_global("a", Value::UserData(UserData::new(&door, 1)));
+  interp.set_global("b", Value::UserData(UserData::new(&door, 1)));
+  assert_eq!(interp.eval_str("(eq a a)"), Ok(Value::Bool(true)));
+  assert_eq!(interp.eval_str("(eq a b)"), Ok(Value::Bool(false)));
+  assert_eq!(interp.eval_str("a").unwrap().to_string(), "#<door>");
+}
+
+#[test]
+fn lazy_stream_test() {
+  let mut interp = Interpreter::new();
+  let pulled = Rc::new(RefCell::new(0));
+  let counter = pulled.clone();
+  let encounters = ["bat", "rat", "slime"].iter().cycle().map(move |name| {
+    *counter.borrow_mut() += 1;
+    Value::String(name.to_string())
+  });
+  interp.set_global("encounters", Value::Lazy(LazySeq::stream(encounters)));
+
+  assert_eq!(interp.eval_str("(nth encounters 4)"), Ok(Value::String("rat".to_string())));
+  assert_eq!(*pulled.borrow(), 5);
+  interp.eval_str("(first encounters)").unwrap();
+  assert_eq!(*pulled.borrow(), 5);
+
+  // Walking a Harp sequence from Rust
+  let squares = match interp.eval_str("(map (lambda (x) (* x x)) (iterate (lambda (x) (+ x 1)) 1))") {
+    Ok(Value::Lazy(seq)) => seq,
+    res => panic!("Expected a lazy sequence, got {:?}", res),
+  };
+  let firsts: Result<Vec<Value>, HarpError> = squares.iter(interp.env()).take(3).collect();
+  assert_eq!(firsts, Ok(vec![Value::Number(1.0), Value::Number(4.0), Value::Number(9.0)]));
+
+  // A long realized sequence drops without recursing through it
+  let long = LazySeq::stream((0..200_000).map(|n| Value::Number(n as f64)));
+  assert_eq!(long.to_vec(interp.env()).map(|xs| xs.len()), Ok(200_000));
+  drop(long);
+}
+
+#[test]
+fn seeded_random_test() {
+  let draws = |seed| {
+    let mut interp = Interpreter::new();
+    interp.seed_random(seed);
+    interp.eval_str("(list (rand/int 1000) (rand/float) (rand/shuffle [1 2 3 4]))").unwrap()
+  };
+  assert_eq!(draws(9), draws(9));
+  assert_ne!(draws(9), draws(10));
+}